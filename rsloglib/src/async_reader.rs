@@ -0,0 +1,270 @@
+use crate::error::{Context, RslogError};
+use crate::filter::Filter;
+use crate::poll::PollState;
+use crate::slowlog::SlowlogRecord;
+use crate::slowlog_reader::{default_slowlog_command, reconnect_reason, slowlog_cmd};
+use futures::stream::{self, Stream};
+use redis::aio::MultiplexedConnection;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub async fn get_slowlog_async(
+    con: &mut MultiplexedConnection,
+    length: u32,
+) -> redis::RedisResult<Vec<SlowlogRecord>> {
+    get_slowlog_with_async(con, &default_slowlog_command(), length).await
+}
+
+/// Same as `get_slowlog_async`, but sends `command` instead of `SLOWLOG GET`
+pub async fn get_slowlog_with_async(
+    con: &mut MultiplexedConnection,
+    command: &[String],
+    length: u32,
+) -> redis::RedisResult<Vec<SlowlogRecord>> {
+    log::debug!("Executing slowlog query");
+    slowlog_cmd(command, length).query_async(con).await
+}
+
+// Takes any connection so it can be tested without a server
+async fn poll(
+    state: &mut PollState,
+    con: &mut impl redis::aio::ConnectionLike,
+    length: u32,
+) -> Result<Vec<SlowlogRecord>, RslogError> {
+    let replies = state.pipeline(length).query_async(con).await;
+    state.take_replies(replies)
+}
+
+/// Same as `SlowlogReader`, but for tokio applications
+pub struct AsyncSlowlogReader {
+    client: redis::Client,
+    connection: MultiplexedConnection,
+    state: PollState,
+}
+
+impl AsyncSlowlogReader {
+    pub async fn new(client: redis::Client) -> Result<AsyncSlowlogReader, RslogError> {
+        Ok(AsyncSlowlogReader {
+            connection: client
+                .get_multiplexed_tokio_connection()
+                .await
+                .context("Can't connect to the server")?,
+            client,
+            state: PollState::default(),
+        })
+    }
+
+    pub async fn get(&mut self) -> Result<Vec<SlowlogRecord>, RslogError> {
+        self.get_with_length(self.state.length).await
+    }
+
+    /// Same as `SlowlogReader::get_with_length`
+    pub async fn get_with_length(&mut self, length: u32) -> Result<Vec<SlowlogRecord>, RslogError> {
+        poll(&mut self.state, &mut self.connection, length).await
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.state.filter = filter
+    }
+
+    /// Sets how many records are requested from the server on every poll
+    pub fn set_length(&mut self, length: u32) {
+        self.state.length = length
+    }
+
+    /// Id of the newest record read so far, -1 if nothing was read yet
+    pub fn last_id(&self) -> i64 {
+        self.state.last_id
+    }
+
+    /// Only records with a greater id are returned from now on, -1 returns everything in slowlog
+    pub fn set_last_id(&mut self, last_id: i64) {
+        self.state.last_id = last_id
+    }
+
+    /// Sets how long `records` waits between polls that returned nothing new, 5 seconds by default
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.state.interval = interval
+    }
+
+    /// Same as `SlowlogReaderBuilder::restart_check_interval`
+    pub fn set_restart_check_interval(&mut self, interval: Option<Duration>) {
+        self.state.restart_check.set_interval(interval)
+    }
+
+    /// Same as `SlowlogReaderBuilder::slowlog_command`
+    pub fn set_slowlog_command(&mut self, command: Vec<String>) {
+        self.state.command = command
+    }
+
+    /// Same as `SlowlogReaderBuilder::reconnect_kinds`
+    pub fn set_reconnect_kinds(&mut self, kinds: Vec<redis::ErrorKind>) {
+        self.state.reconnect_kinds = kinds
+    }
+
+    /// Whether `redis_error_handler` reconnects after the error
+    pub fn reconnects_on(&self, e: &RslogError) -> bool {
+        self.state.reconnects_on(e)
+    }
+
+    /// Total number of records that fell off the slowlog before they could be read
+    pub fn skipped_records(&self) -> u64 {
+        self.state.skipped_records
+    }
+
+    pub async fn update_connection(&mut self) -> Result<(), RslogError> {
+        self.connection = self
+            .client
+            .get_multiplexed_tokio_connection()
+            .await
+            .context("Can't reconnect to the server")?;
+        Ok(())
+    }
+
+    /// Same as `SlowlogReader::redis_error_handler`
+    pub async fn redis_error_handler(&mut self, e: RslogError) -> Result<(), RslogError> {
+        if !self.state.reconnects_on(&e) {
+            return Err(e);
+        }
+        log::warn!("Reconnecting after {}: {}", reconnect_reason(&e), e);
+        self.update_connection().await
+    }
+
+    /// Returns an endless stream of new records, oldest first, that behaves like `SlowlogReader::records`
+    pub fn records(&mut self) -> impl Stream<Item = Result<SlowlogRecord, RslogError>> + '_ {
+        stream::unfold(
+            (self, VecDeque::new(), false),
+            |(reader, mut pending, mut failed)| async move {
+                loop {
+                    if let Some(r) = pending.pop_front() {
+                        return Some((Ok(r), (reader, pending, failed)));
+                    }
+                    if failed {
+                        tokio::time::sleep(reader.state.interval).await;
+                        failed = false
+                    }
+                    let error = match reader.get().await {
+                        Ok(records) if records.is_empty() => {
+                            tokio::time::sleep(reader.state.interval).await;
+                            continue;
+                        }
+                        Ok(records) => {
+                            // records come newest first
+                            pending.extend(records.into_iter().rev());
+                            continue;
+                        }
+                        Err(e) => match reader.redis_error_handler(e).await {
+                            Ok(()) => continue,
+                            Err(e) => e,
+                        },
+                    };
+                    return Some((Err(error), (reader, pending, true)));
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use redis::Value as v;
+
+    fn entry(id: i64) -> redis::Value {
+        v::Bulk(vec![
+            v::Int(id),
+            v::Int(1),
+            v::Int(10),
+            v::Bulk(vec![v::Data(b"GET".to_vec()), v::Data(b"key".to_vec())]),
+            v::Data(b"127.0.0.1:10000".to_vec()),
+            v::Data(b"".to_vec()),
+        ])
+    }
+
+    fn reply(uptime: u64, ids: &[i64]) -> redis::RedisResult<Vec<redis::Value>> {
+        Ok(vec![
+            v::Data(format!("# Server\r\nuptime_in_seconds:{}\r\n", uptime).into_bytes()),
+            v::Bulk(ids.iter().map(|&id| entry(id)).collect()),
+        ])
+    }
+
+    // Answers each poll with the next of the given replies
+    struct MockConnection(VecDeque<redis::RedisResult<Vec<redis::Value>>>);
+
+    impl redis::aio::ConnectionLike for MockConnection {
+        fn req_packed_command<'a>(
+            &'a mut self,
+            _cmd: &'a redis::Cmd,
+        ) -> redis::RedisFuture<'a, redis::Value> {
+            // polls are pipelined, a single command means poll stopped sending pipelines
+            Box::pin(async {
+                Err(redis::RedisError::from((
+                    redis::ErrorKind::ClientError,
+                    "MockConnection only answers pipelines",
+                )))
+            })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _cmd: &'a redis::Pipeline,
+            offset: usize,
+            count: usize,
+        ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+            assert_eq!((offset, count), (0, 2));
+            let reply = self.0.pop_front().expect("no more replies");
+            Box::pin(async move { reply })
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    fn ids(records: Vec<SlowlogRecord>) -> Vec<u64> {
+        records.into_iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn restart() {
+        let mut state = PollState::default();
+        let mut con = MockConnection(vec![reply(100, &[2, 1]), reply(5, &[1])].into());
+        futures::executor::block_on(async {
+            assert_eq!(ids(poll(&mut state, &mut con, 128).await.unwrap()), [2, 1]);
+            assert_eq!(ids(poll(&mut state, &mut con, 128).await.unwrap()), [1]);
+        });
+        assert_eq!(state.last_id, 1);
+    }
+
+    #[test]
+    fn reconnect() {
+        let mut state = PollState::default();
+        let lost = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        let loading = redis::RedisError::from((
+            redis::ErrorKind::BusyLoadingError,
+            "An error was signalled by the server",
+            "LOADING".to_owned(),
+        ));
+        let denied = redis::RedisError::from((
+            redis::ErrorKind::ResponseError,
+            "An error was signalled by the server",
+            "ERR unknown command".to_owned(),
+        ));
+        let mut con = MockConnection(vec![reply(100, &[2, 1]), Err(lost.into())].into());
+        // a new connection after the lost one, to a server that is still loading
+        let mut new_con =
+            MockConnection(vec![Err(loading), reply(101, &[3, 2, 1]), Err(denied)].into());
+        futures::executor::block_on(async {
+            poll(&mut state, &mut con, 128).await.unwrap();
+            let e = poll(&mut state, &mut con, 128).await.unwrap_err();
+            assert!(matches!(e, RslogError::Connection { .. }));
+            assert!(state.reconnects_on(&e));
+            let e = poll(&mut state, &mut new_con, 128).await.unwrap_err();
+            assert!(state.reconnects_on(&e));
+            // records read before the connection was lost aren't returned again
+            assert_eq!(ids(poll(&mut state, &mut new_con, 128).await.unwrap()), [3]);
+            let e = poll(&mut state, &mut new_con, 128).await.unwrap_err();
+            assert!(!state.reconnects_on(&e));
+        });
+    }
+}
@@ -0,0 +1,67 @@
+fn parse_cluster_nodes(nodes: &str) -> redis::RedisResult<Vec<(String, u16)>> {
+    let mut masters = Vec::new();
+    for line in nodes.lines().filter(|l| !l.is_empty()) {
+        let mut fields = line.split(' ');
+        let addr = fields.nth(1);
+        let flags = fields.next();
+        let (addr, flags) = match (addr, flags) {
+            (Some(addr), Some(flags)) => (addr, flags),
+            _ => {
+                return Err(redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "Unexpected line in cluster nodes",
+                    line.to_owned(),
+                )))
+            }
+        };
+        let flags: Vec<_> = flags.split(',').collect();
+        if !flags.contains(&"master") || flags.iter().any(|f| matches!(*f, "fail" | "noaddr")) {
+            continue;
+        }
+        // address looks like ip:port@cport[,hostname]
+        let (host, port) = addr
+            .split('@')
+            .next()
+            .and_then(|a| a.rsplit_once(':'))
+            .and_then(|(host, port)| Some((host.to_owned(), port.parse().ok()?)))
+            .ok_or_else(|| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "Unexpected node address in cluster nodes",
+                    addr.to_owned(),
+                ))
+            })?;
+        masters.push((host, port))
+    }
+    Ok(masters)
+}
+
+/// Returns addresses of all healthy master nodes of the cluster the connection belongs to
+pub fn cluster_masters(con: &mut redis::Connection) -> redis::RedisResult<Vec<(String, u16)>> {
+    log::debug!("Executing cluster nodes query");
+    let nodes: String = redis::cmd("CLUSTER").arg("NODES").query(con)?;
+    parse_cluster_nodes(&nodes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masters() {
+        let nodes = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003@31003 master,fail - 0 1426238318243 3 connected 10923-16383
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001,node-1 myself,master - 0 0 1 connected 0-5460
+";
+        assert_eq!(
+            parse_cluster_nodes(nodes).unwrap(),
+            [
+                ("127.0.0.1".to_owned(), 30002),
+                ("127.0.0.1".to_owned(), 30001)
+            ]
+        );
+        assert!(parse_cluster_nodes("garbage").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+
+/// Redis error together with what was being done when it happened
+#[derive(Debug)]
+pub enum RslogError {
+    /// The server can't be reached, didn't answer in time or the connection was lost,
+    /// usually worth retrying
+    Connection {
+        context: String,
+        source: redis::RedisError,
+    },
+    /// The server rejected the credentials or the user lacks permissions
+    Auth {
+        context: String,
+        source: redis::RedisError,
+    },
+    /// Any other error reported by the server or while parsing its answer
+    Redis {
+        context: String,
+        source: redis::RedisError,
+    },
+}
+
+impl RslogError {
+    pub fn new(context: &str, source: redis::RedisError) -> RslogError {
+        let context = context.to_owned();
+        match source.kind() {
+            redis::ErrorKind::IoError => RslogError::Connection { context, source },
+            redis::ErrorKind::AuthenticationFailed => RslogError::Auth { context, source },
+            // redis doesn't have kinds for these codes
+            redis::ErrorKind::ExtensionError
+                if matches!(source.code(), Some("NOAUTH" | "WRONGPASS" | "NOPERM")) =>
+            {
+                RslogError::Auth { context, source }
+            }
+            _ => RslogError::Redis { context, source },
+        }
+    }
+
+    pub fn redis_error(&self) -> &redis::RedisError {
+        match self {
+            RslogError::Connection { source, .. }
+            | RslogError::Auth { source, .. }
+            | RslogError::Redis { source, .. } => source,
+        }
+    }
+
+    pub fn context(&self) -> &str {
+        match self {
+            RslogError::Connection { context, .. }
+            | RslogError::Auth { context, .. }
+            | RslogError::Redis { context, .. } => context,
+        }
+    }
+}
+
+impl fmt::Display for RslogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context(), self.redis_error())
+    }
+}
+
+impl std::error::Error for RslogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.redis_error())
+    }
+}
+
+/// Adds context to redis errors
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T, RslogError>;
+}
+
+impl<T> Context<T> for redis::RedisResult<T> {
+    fn context(self, context: &str) -> Result<T, RslogError> {
+        self.map_err(|e| RslogError::new(context, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let io = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(matches!(
+            RslogError::new("connecting", io.into()),
+            RslogError::Connection { .. }
+        ));
+        let wrongpass =
+            redis::parse_redis_value(b"-WRONGPASS invalid username-password pair\r\n").unwrap_err();
+        assert!(matches!(
+            RslogError::new("connecting", wrongpass),
+            RslogError::Auth { .. }
+        ));
+        let e = RslogError::new(
+            "Can't read slowlog",
+            redis::RedisError::from((redis::ErrorKind::TypeError, "bad reply")),
+        );
+        assert!(matches!(e, RslogError::Redis { .. }));
+        assert_eq!(e.to_string(), "Can't read slowlog: bad reply");
+    }
+}
@@ -0,0 +1,121 @@
+use crate::slowlog::SlowlogRecord;
+
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Minimal duration of a record in microseconds
+    pub min_duration: u64,
+    /// Minimal durations for some command names, in place of `min_duration`
+    pub thresholds: Vec<(String, u64)>,
+    /// Command names to keep, any when empty
+    pub commands: Vec<String>,
+    /// Command names to skip
+    pub exclude_commands: Vec<String>,
+    /// Pattern the space separated command with its arguments must match
+    pub command_regex: Option<regex::Regex>,
+    /// Client address, matches any port when given without one
+    pub client: Option<String>,
+    pub client_name: Option<String>,
+}
+
+fn client_matches(client: &str, socket: &str) -> bool {
+    let trim = |a: &str| a.trim_start_matches('[').trim_end_matches(']').to_owned();
+    socket == client
+        || socket
+            .rsplit_once(':')
+            .is_some_and(|(ip, _)| trim(ip) == trim(client))
+}
+
+impl Filter {
+    pub fn matches(&self, r: &SlowlogRecord) -> bool {
+        let name = r.command.first().map_or("", |c| c.as_str());
+        let min_duration = self
+            .thresholds
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(name))
+            .map_or(self.min_duration, |&(_, d)| d);
+        r.duration >= min_duration
+            && (self.commands.is_empty()
+                || self.commands.iter().any(|c| c.eq_ignore_ascii_case(name)))
+            && !self
+                .exclude_commands
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(name))
+            && self
+                .command_regex
+                .as_ref()
+                .is_none_or(|re| re.is_match(&r.command.join(" ")))
+            && self
+                .client
+                .as_ref()
+                .is_none_or(|c| client_matches(c, &r.client_socket))
+            && self
+                .client_name
+                .as_ref()
+                .is_none_or(|n| *n == r.client_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(command: &str, client_socket: &str) -> SlowlogRecord {
+        SlowlogRecord {
+            duration: 100,
+            command: command.split(' ').map(|s| s.to_owned()).collect(),
+            client_socket: client_socket.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let f = Filter {
+            commands: vec!["get".to_owned(), "set".to_owned()],
+            exclude_commands: vec!["set".to_owned()],
+            ..Default::default()
+        };
+        assert!(f.matches(&record("GET key", "")));
+        assert!(!f.matches(&record("SET key value", "")));
+        assert!(!f.matches(&record("KEYS *", "")));
+    }
+
+    #[test]
+    fn thresholds() {
+        let f = Filter {
+            min_duration: 50,
+            thresholds: vec![("keys".to_owned(), 1000), ("get".to_owned(), 10)],
+            ..Default::default()
+        };
+        assert!(!f.matches(&record("KEYS *", "")));
+        assert!(f.matches(&record("GET key", "")));
+        assert!(f.matches(&record("SET key value", "")));
+        let f = Filter {
+            min_duration: 200,
+            ..f
+        };
+        assert!(!f.matches(&record("SET key value", "")));
+        assert!(f.matches(&record("GET key", "")));
+    }
+
+    #[test]
+    fn client() {
+        let f = Filter {
+            client: Some("10.0.0.5".to_owned()),
+            ..Default::default()
+        };
+        assert!(f.matches(&record("GET key", "10.0.0.5:54321")));
+        assert!(!f.matches(&record("GET key", "10.0.0.50:54321")));
+        let f = Filter {
+            client: Some("10.0.0.5:1".to_owned()),
+            ..Default::default()
+        };
+        assert!(f.matches(&record("GET key", "10.0.0.5:1")));
+        assert!(!f.matches(&record("GET key", "10.0.0.5:2")));
+        let f = Filter {
+            client: Some("::1".to_owned()),
+            ..Default::default()
+        };
+        assert!(f.matches(&record("GET key", "[::1]:6000")));
+    }
+}
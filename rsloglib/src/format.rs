@@ -0,0 +1,180 @@
+use crate::slowlog::SlowlogRecord;
+
+// Record formats of the rslog command line tool. `time` is written as given,
+// `r.time.to_string()` for the unix timestamp or a formatted date.
+
+/// `[TIME] id: ID, duration: DURATION, client: CLIENT, client_name: NAME, command: [ARGS]`
+/// separated by tabs
+pub fn format_text(r: &SlowlogRecord, time: &str) -> String {
+    format!(
+        "[{}] id: {},\tduration: {},\tclient: {},\tclient_name: {},\tcommand: {:?}",
+        time, r.id, r.duration, r.client_socket, r.client_name, r.command
+    )
+}
+
+/// `TIME,ID,DURATION,CLIENT,NAME,COMMAND` with arguments of the command separated by spaces
+pub fn format_csv(r: &SlowlogRecord, time: &str) -> String {
+    format!(
+        "{},{},{},{},{},{}",
+        csv_field(time),
+        r.id,
+        r.duration,
+        csv_field(&r.client_socket),
+        csv_field(&r.client_name),
+        csv_field(&r.command.join(" "))
+    )
+}
+
+/// Same as `format_csv`, but separated by tabs
+pub fn format_tsv(r: &SlowlogRecord, time: &str) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        tsv_field(time),
+        r.id,
+        r.duration,
+        tsv_field(&r.client_socket),
+        tsv_field(&r.client_name),
+        tsv_field(&r.command.join(" "))
+    )
+}
+
+/// `time=TIME id=ID duration=DURATION client=CLIENT client_name=NAME command=COMMAND`
+pub fn format_logfmt(r: &SlowlogRecord, time: &str) -> String {
+    format!(
+        "time={} id={} duration={} client={} client_name={} command={}",
+        logfmt_value(time),
+        r.id,
+        r.duration,
+        logfmt_value(&r.client_socket),
+        logfmt_value(&r.client_name),
+        logfmt_value(&r.command.join(" "))
+    )
+}
+
+/// JSON object with the fields of the record, the time is a unix timestamp
+#[cfg(feature = "json")]
+pub fn format_json(r: &SlowlogRecord) -> String {
+    serde_json::to_string(r).unwrap()
+}
+
+/// Quotes a field as described in RFC 4180 if it contains a separator, quote or line break
+pub fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Replaces tabs and line breaks, TSV has no quoting
+pub fn tsv_field(field: &str) -> String {
+    field.replace(&['\t', '\r', '\n'][..], " ")
+}
+
+/// Quotes a value if it can't be represented in logfmt as is
+pub fn logfmt_value(value: &str) -> String {
+    if value.contains(&[' ', '=', '"', '\\', '\n', '\r', '\t'][..]) {
+        format!("{:?}", value)
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Duration in the largest of us, ms and s that keeps it above 1, like 1.5ms
+pub fn human_duration(microseconds: u64) -> String {
+    // tenths of a millisecond, rounded before picking the unit so 999.96ms isn't shown as 1000.0ms
+    let tenths = microseconds.saturating_add(50) / 100;
+    if microseconds < 1000 {
+        format!("{}us", microseconds)
+    } else if tenths < 10_000 {
+        format!("{}.{}ms", tenths / 10, tenths % 10)
+    } else {
+        format!("{:.1}s", microseconds as f64 / 1_000_000.0)
+    }
+}
+
+/// Shortens arguments longer than `max_arg_len` characters and replaces arguments after
+/// `max_args` with a count, zero disables a limit. Returns whether anything was cut.
+pub fn truncate_command(
+    command: &[String],
+    max_arg_len: usize,
+    max_args: usize,
+) -> (Vec<String>, bool) {
+    let mut truncated = false;
+    let kept = if max_args > 0 {
+        max_args.min(command.len())
+    } else {
+        command.len()
+    };
+    let mut result: Vec<_> = command[..kept]
+        .iter()
+        .map(|arg| match arg.char_indices().nth(max_arg_len) {
+            Some((end, _)) if max_arg_len > 0 => {
+                truncated = true;
+                format!("{}…", &arg[..end])
+            }
+            _ => arg.clone(),
+        })
+        .collect();
+    if kept < command.len() {
+        truncated = true;
+        result.push(format!("(+{} more)", command.len() - kept))
+    }
+    (result, truncated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record() -> SlowlogRecord {
+        SlowlogRecord {
+            id: 7,
+            time: 1700000000,
+            duration: 1500,
+            command: vec!["SET".to_owned(), "k,1".to_owned(), "a \"b\"".to_owned()],
+            client_socket: "127.0.0.1:1000".to_owned(),
+            client_name: "".to_owned(),
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let r = record();
+        assert_eq!(
+            format_text(&r, "1700000000"),
+            "[1700000000] id: 7,\tduration: 1500,\tclient: 127.0.0.1:1000,\tclient_name: ,\tcommand: [\"SET\", \"k,1\", \"a \\\"b\\\"\"]"
+        );
+        assert_eq!(
+            format_csv(&r, "1700000000"),
+            "1700000000,7,1500,127.0.0.1:1000,,\"SET k,1 a \"\"b\"\"\""
+        );
+        assert_eq!(
+            format_tsv(&r, "2023-11-14 22:13:20"),
+            "2023-11-14 22:13:20\t7\t1500\t127.0.0.1:1000\t\tSET k,1 a \"b\""
+        );
+        assert_eq!(
+            format_logfmt(&r, "1700000000"),
+            "time=1700000000 id=7 duration=1500 client=127.0.0.1:1000 client_name= command=\"SET k,1 a \\\"b\\\"\""
+        );
+        assert_eq!(
+            truncate_command(&r.command, 2, 2),
+            (
+                vec!["SE…".to_owned(), "k,…".to_owned(), "(+1 more)".to_owned()],
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn human_durations() {
+        assert_eq!(human_duration(999), "999us");
+        assert_eq!(human_duration(1000), "1.0ms");
+        assert_eq!(human_duration(1500), "1.5ms");
+        assert_eq!(human_duration(999_949), "999.9ms");
+        assert_eq!(human_duration(999_950), "1.0s");
+        assert_eq!(human_duration(999_999), "1.0s");
+        assert_eq!(human_duration(1_000_000), "1.0s");
+        assert_eq!(human_duration(u64::MAX), "18446744073709.6s");
+    }
+}
@@ -1,5 +1,24 @@
+#[cfg(feature = "async")]
+mod async_reader;
+mod cluster;
+mod error;
+mod filter;
+mod format;
+mod poll;
+mod redact;
+mod sentinel;
 mod slowlog;
 mod slowlog_reader;
+mod source;
 
+#[cfg(feature = "async")]
+pub use async_reader::*;
+pub use cluster::*;
+pub use error::*;
+pub use filter::*;
+pub use format::*;
+pub use redact::*;
+pub use sentinel::*;
 pub use slowlog::*;
-pub use slowlog_reader::*;
\ No newline at end of file
+pub use slowlog_reader::*;
+pub use source::*;
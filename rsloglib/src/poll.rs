@@ -0,0 +1,306 @@
+use crate::error::{Context, RslogError};
+use crate::filter::Filter;
+use crate::slowlog::{decode_entries, SlowlogRecord};
+use crate::slowlog_reader::{
+    default_reconnect_kinds, default_slowlog_command, parse_uptime, should_reconnect, slowlog_cmd,
+};
+use std::time::{Duration, Instant};
+
+// Decides on which polls the server uptime is read to detect restarts
+pub(crate) struct RestartCheck {
+    interval: Option<Duration>,
+    last: Option<Instant>,
+}
+
+impl RestartCheck {
+    pub(crate) fn new(interval: Option<Duration>) -> RestartCheck {
+        RestartCheck {
+            interval,
+            last: None,
+        }
+    }
+
+    pub(crate) fn set_interval(&mut self, interval: Option<Duration>) {
+        self.interval = interval
+    }
+
+    pub(crate) fn due(&mut self) -> bool {
+        let due = match self.interval {
+            None => false,
+            Some(interval) => self.last.is_none_or(|last| last.elapsed() >= interval),
+        };
+        if due {
+            self.last = Some(Instant::now())
+        }
+        due
+    }
+}
+
+// What a reader keeps between polls. The sync and async readers only differ in how they
+// send the pipeline, building it and handling the replies is done here for both.
+pub(crate) struct PollState {
+    pub(crate) last_id: i64,
+    pub(crate) length: u32,
+    pub(crate) command: Vec<String>,
+    pub(crate) uptime: u64,
+    pub(crate) filter: Filter,
+    pub(crate) skipped_records: u64,
+    pub(crate) interval: Duration,
+    pub(crate) restart_check: RestartCheck,
+    pub(crate) reconnect_kinds: Vec<redis::ErrorKind>,
+}
+
+impl Default for PollState {
+    fn default() -> PollState {
+        PollState {
+            last_id: -1,
+            length: 128,
+            command: default_slowlog_command(),
+            uptime: 0,
+            filter: Filter::default(),
+            skipped_records: 0,
+            interval: Duration::from_secs(5),
+            restart_check: RestartCheck::new(Some(Duration::ZERO)),
+            reconnect_kinds: default_reconnect_kinds(),
+        }
+    }
+}
+
+impl PollState {
+    // The slowlog command, preceded by INFO SERVER when the uptime is due to be read,
+    // so a poll is always one round trip
+    pub(crate) fn pipeline(&mut self, length: u32) -> redis::Pipeline {
+        log::debug!("Executing slowlog query");
+        let mut pipe = redis::pipe();
+        if self.restart_check.due() {
+            pipe.cmd("INFO").arg("SERVER");
+        }
+        pipe.add_command(slowlog_cmd(&self.command, length));
+        pipe
+    }
+
+    // New records from the replies to `pipeline`, newest first
+    pub(crate) fn take_replies(
+        &mut self,
+        replies: redis::RedisResult<Vec<redis::Value>>,
+    ) -> Result<Vec<SlowlogRecord>, RslogError> {
+        let mut replies = replies.context("Can't read slowlog")?;
+        let sl = replies.pop().unwrap_or(redis::Value::Nil);
+        if let Some(server_info) = replies.pop() {
+            let uptime = redis::from_redis_value::<String>(&server_info)
+                .and_then(|info| parse_uptime(&info))
+                .context("Can't read slowlog")?;
+            self.check_for_restart(uptime)
+        }
+        let filter = &self.filter;
+        let (new_records, missing_records) = take_new_records_from(
+            &mut self.last_id,
+            decode_entries(sl).context("Can't read slowlog")?,
+            |r| filter.matches(r),
+        );
+        self.skipped_records += missing_records;
+        Ok(new_records)
+    }
+
+    pub(crate) fn reconnects_on(&self, e: &RslogError) -> bool {
+        should_reconnect(&self.reconnect_kinds, e)
+    }
+
+    fn check_for_restart(&mut self, uptime: u64) {
+        if uptime < self.uptime {
+            self.last_id = -1;
+            log::info!("Redis server restart detected")
+        }
+        self.uptime = uptime;
+    }
+}
+
+/// Returns records from `sl`, newest first, that are newer than `last_id` and that `keep`
+/// returns true for, and moves `last_id` to the newest one. The second value is the number
+/// of records that fell off the slowlog since the last poll. Records are taken one by one
+/// up to the first one that was already seen, so older ones are never decoded when `sl`
+/// decodes lazily, like `decode_entries` does. `sl` has to be in the order of `SLOWLOG GET`,
+/// newest first by id, which Redis keeps as it adds every record to the head of its list,
+/// and records are returned in that order without sorting.
+pub(crate) fn take_new_records_from(
+    last_id: &mut i64,
+    sl: impl Iterator<Item = SlowlogRecord>,
+    mut keep: impl FnMut(&SlowlogRecord) -> bool,
+) -> (Vec<SlowlogRecord>, u64) {
+    let mut new_records = Vec::new();
+    let mut newest_id = None;
+    let mut oldest_id = None;
+    for record in sl {
+        if newest_id.is_none() {
+            // ids only grow, so a lower one means the slowlog was reset since the last poll.
+            // A reset followed by more records than were there before can't be detected.
+            if (record.id as i64) < *last_id {
+                log::info!("Slowlog reset detected");
+                *last_id = -1
+            }
+            newest_id = Some(record.id as i64)
+        }
+        oldest_id = Some(record.id as i64);
+        if record.id as i64 <= *last_id {
+            break;
+        }
+        if keep(&record) {
+            new_records.push(record)
+        }
+    }
+    let mut missing_records = 0;
+    if let Some(oldest_id) = oldest_id {
+        if *last_id >= 0 && oldest_id - 1 > *last_id {
+            missing_records = (oldest_id - 1 - *last_id) as u64;
+            log::warn!(
+                "{} records skiped, consider increasing the number of fetched records",
+                missing_records
+            )
+        };
+    };
+    if let Some(newest_id) = newest_id {
+        *last_id = newest_id.max(*last_id)
+    }
+    (new_records, missing_records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn records(ids: &[u64]) -> Vec<SlowlogRecord> {
+        ids.iter()
+            .map(|&id| SlowlogRecord {
+                id,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn take_new_records(last_id: &mut i64, sl: Vec<SlowlogRecord>) -> (Vec<SlowlogRecord>, u64) {
+        take_new_records_from(last_id, sl.into_iter(), |_| true)
+    }
+
+    fn ids((records, _): (Vec<SlowlogRecord>, u64)) -> Vec<u64> {
+        records.into_iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn new_records() {
+        let mut last_id = -1;
+        assert_eq!(
+            ids(take_new_records(&mut last_id, records(&[2, 1, 0]))),
+            [2, 1, 0]
+        );
+        assert_eq!(
+            ids(take_new_records(&mut last_id, records(&[2, 1, 0]))),
+            [0u64; 0]
+        );
+        assert_eq!(
+            ids(take_new_records(&mut last_id, records(&[4, 3, 2]))),
+            [4, 3]
+        );
+        assert_eq!(last_id, 4);
+    }
+
+    #[test]
+    fn external_reset() {
+        let mut last_id = -1;
+        take_new_records(&mut last_id, records(&[5, 4, 3]));
+        assert_eq!(ids(take_new_records(&mut last_id, records(&[]))), [0u64; 0]);
+        assert_eq!(
+            ids(take_new_records(&mut last_id, records(&[1, 0]))),
+            [1, 0]
+        );
+        assert_eq!(
+            ids(take_new_records(&mut last_id, records(&[2, 1, 0]))),
+            [2]
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut last_id = -1;
+        assert_eq!(take_new_records(&mut last_id, records(&[1, 0])).1, 0);
+        assert_eq!(take_new_records(&mut last_id, records(&[3, 2])).1, 0);
+        assert_eq!(take_new_records(&mut last_id, records(&[9, 8])).1, 4);
+    }
+
+    #[test]
+    fn bounded_catch_up() {
+        // a million records, newest first, of which only the newest thousand weren't seen
+        // and every hundredth of them is slow enough to keep
+        let decoded = std::cell::Cell::new(0);
+        let sl = (1..=1_000_000u64).rev().map(|id| {
+            decoded.set(decoded.get() + 1);
+            SlowlogRecord {
+                id,
+                duration: if id % 100 == 0 { 10_000 } else { 10 },
+                ..Default::default()
+            }
+        });
+        let mut last_id = 999_000;
+        let (kept, missing) = take_new_records_from(&mut last_id, sl, |r| r.duration > 1000);
+        assert_eq!(kept.len(), 10);
+        assert_eq!(decoded.get(), 1001);
+        assert_eq!((last_id, missing), (1_000_000, 0));
+    }
+
+    // Answers polls like a server would and counts the requests
+    #[derive(Default)]
+    struct MockConnection {
+        commands: usize,
+        pipelines: usize,
+    }
+
+    impl redis::ConnectionLike for MockConnection {
+        fn req_packed_command(&mut self, _cmd: &[u8]) -> redis::RedisResult<redis::Value> {
+            self.commands += 1;
+            Ok(redis::Value::Nil)
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _cmd: &[u8],
+            offset: usize,
+            count: usize,
+        ) -> redis::RedisResult<Vec<redis::Value>> {
+            use redis::Value as v;
+            self.pipelines += 1;
+            assert_eq!((offset, count), (0, 2));
+            Ok(vec![
+                v::Data(b"# Server\r\nuptime_in_seconds:42\r\n".to_vec()),
+                v::Bulk(vec![v::Bulk(vec![
+                    v::Int(7),
+                    v::Int(1),
+                    v::Int(10),
+                    v::Bulk(vec![v::Data(b"GET".to_vec()), v::Data(b"key".to_vec())]),
+                    v::Data(b"127.0.0.1:10000".to_vec()),
+                    v::Data(b"".to_vec()),
+                ])]),
+            ])
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn single_round_trip() {
+        let mut con = MockConnection::default();
+        let mut state = PollState::default();
+        let replies = state.pipeline(128).query(&mut con);
+        assert_eq!((con.pipelines, con.commands), (1, 0));
+        assert_eq!(ids((state.take_replies(replies).unwrap(), 0)), [7]);
+        assert_eq!(state.uptime, 42);
+    }
+}
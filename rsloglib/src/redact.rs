@@ -0,0 +1,106 @@
+use crate::slowlog::SlowlogRecord;
+
+/// Hides an argument of a command, or all of them if there is no position.
+/// Positions start from 1 for the first argument after the command name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedactRule {
+    pub command: String,
+    pub position: Option<usize>,
+}
+
+impl std::str::FromStr for RedactRule {
+    type Err = String;
+
+    /// Parses `COMMAND:POSITION`, where position is a number or `*` for all arguments
+    fn from_str(rule: &str) -> Result<RedactRule, String> {
+        let (command, position) = rule
+            .rsplit_once(':')
+            .ok_or_else(|| format!("Redact rule must be in COMMAND:POSITION form: {}", rule))?;
+        let position = match position {
+            "*" => None,
+            p => match p.parse() {
+                Ok(p) if p > 0 => Some(p),
+                _ => {
+                    return Err(format!(
+                        "Invalid argument position in redact rule: {}",
+                        rule
+                    ))
+                }
+            },
+        };
+        Ok(RedactRule {
+            command: command.to_owned(),
+            position,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Redaction {
+    pub rules: Vec<RedactRule>,
+}
+
+impl Redaction {
+    /// Rules for commands that always carry secrets
+    pub fn default_rules() -> Vec<RedactRule> {
+        vec![RedactRule {
+            command: "AUTH".to_owned(),
+            position: None,
+        }]
+    }
+
+    pub fn apply(&self, r: &mut SlowlogRecord) {
+        let (name, args) = match r.command.split_first_mut() {
+            Some(command) => command,
+            None => return,
+        };
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.command.eq_ignore_ascii_case(name))
+        {
+            match rule.position {
+                None => args.iter_mut().for_each(|a| *a = "***".to_owned()),
+                Some(p) => {
+                    if let Some(a) = args.get_mut(p - 1) {
+                        *a = "***".to_owned()
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn redact(redaction: &Redaction, command: &[&str]) -> Vec<String> {
+        let mut r = SlowlogRecord {
+            command: command.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        };
+        redaction.apply(&mut r);
+        r.command
+    }
+
+    #[test]
+    fn rules() {
+        let mut redaction = Redaction {
+            rules: Redaction::default_rules(),
+        };
+        redaction.rules.push("set:2".parse().unwrap());
+        assert_eq!(
+            redact(&redaction, &["auth", "user", "pass"]),
+            ["auth", "***", "***"]
+        );
+        assert_eq!(
+            redact(&redaction, &["SET", "session:token", "value"]),
+            ["SET", "session:token", "***"]
+        );
+        assert_eq!(redact(&redaction, &["SET", "key"]), ["SET", "key"]);
+        assert_eq!(redact(&redaction, &["GET", "key"]), ["GET", "key"]);
+        assert!("set".parse::<RedactRule>().is_err());
+        assert!("set:0".parse::<RedactRule>().is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+/// Finds the current master of a group monitored by Redis Sentinel
+#[derive(Clone)]
+pub struct Sentinel {
+    sentinels: Vec<(String, u16)>,
+    master_name: String,
+    master_info: redis::ConnectionInfo,
+}
+
+impl Sentinel {
+    /// `master_info` is used to connect to the master, only its address is replaced
+    pub fn new(
+        sentinels: Vec<(String, u16)>,
+        master_name: String,
+        master_info: redis::ConnectionInfo,
+    ) -> Sentinel {
+        Sentinel {
+            sentinels,
+            master_name,
+            master_info,
+        }
+    }
+
+    fn query_master_addr(
+        &self,
+        sentinel: &(String, u16),
+        timeout: Duration,
+    ) -> redis::RedisResult<(String, u16)> {
+        let client = redis::Client::open(redis::ConnectionInfo {
+            addr: Box::new(redis::ConnectionAddr::Tcp(sentinel.0.clone(), sentinel.1)),
+            db: 0,
+            username: None,
+            passwd: None,
+        })?;
+        let mut con = client.get_connection_with_timeout(timeout)?;
+        let addr: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("get-master-addr-by-name")
+            .arg(&self.master_name)
+            .query(&mut con)?;
+        addr.ok_or_else(|| {
+            redis::RedisError::from((
+                redis::ErrorKind::ResponseError,
+                "Sentinel doesn't know the master",
+                self.master_name.clone(),
+            ))
+        })
+    }
+
+    /// Asks sentinels one by one for the master address until one of them answers
+    pub fn master_connection_info(
+        &self,
+        timeout: Duration,
+    ) -> redis::RedisResult<redis::ConnectionInfo> {
+        let mut last_error =
+            redis::RedisError::from((redis::ErrorKind::InvalidClientConfig, "No sentinels given"));
+        for sentinel in &self.sentinels {
+            match self.query_master_addr(sentinel, timeout) {
+                Ok((host, port)) => {
+                    log::debug!("Master {} is at {}:{}", self.master_name, host, port);
+                    let addr = match *self.master_info.addr {
+                        redis::ConnectionAddr::TcpTls { insecure, .. } => {
+                            redis::ConnectionAddr::TcpTls {
+                                host,
+                                port,
+                                insecure,
+                            }
+                        }
+                        _ => redis::ConnectionAddr::Tcp(host, port),
+                    };
+                    return Ok(redis::ConnectionInfo {
+                        addr: Box::new(addr),
+                        ..self.master_info.clone()
+                    });
+                }
+                Err(e) => {
+                    log::warn!("Sentinel {}:{} failed: {}", sentinel.0, sentinel.1, e);
+                    last_error = e
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
@@ -1,40 +1,215 @@
 
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+/// Equality and hashing include all fields, the id too, so records of the same command
+/// logged twice are different, `same_content` compares them without the id
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SlowlogRecord {
     pub id: u64,
+    /// Unix timestamp in seconds, Redis doesn't log finer times. Ids grow with every record,
+    /// so they tell the order of records logged in the same second.
     pub time: u64,
-    pub duration: u32,
+    /// Microseconds
+    pub duration: u64,
     pub command: Vec<String>,
     pub client_socket: String,
     pub client_name: String,
 }
 
+// Describes a value without its contents, which can be long command arguments
+fn shape(v: &redis::Value) -> String {
+    match v {
+        redis::Value::Nil => "nil".to_owned(),
+        redis::Value::Int(i) => format!("integer {}", i),
+        redis::Value::Data(data) => format!("string of {} bytes", data.len()),
+        redis::Value::Bulk(items) => format!(
+            "array [{}]",
+            items.iter().map(shape).collect::<Vec<_>>().join(", ")
+        ),
+        redis::Value::Status(status) => format!("status {}", status),
+        redis::Value::Okay => "OK".to_owned(),
+    }
+}
+
+fn invalid_entry(detail: String) -> redis::RedisError {
+    redis::RedisError::from((redis::ErrorKind::TypeError, "Invalid slowlog entry", detail))
+}
+
+// Redis shortens arguments over 128 bytes and commands over 32 arguments before logging them,
+// so for those both counts are lower bounds
+impl SlowlogRecord {
+    /// Number of arguments, including the command name
+    pub fn arg_count(&self) -> usize {
+        self.command.len()
+    }
+
+    /// Estimated size of the command, the sum of the lengths of its arguments in bytes
+    pub fn payload_bytes(&self) -> usize {
+        self.command.iter().map(|arg| unescape(arg).len()).sum()
+    }
+
+    /// Arguments of the command as the bytes Redis logged, `command` holds them escaped
+    pub fn command_bytes(&self) -> Vec<Vec<u8>> {
+        self.command.iter().map(|arg| unescape(arg)).collect()
+    }
+
+    /// Whether both records are equal in all fields but the id
+    pub fn same_content(&self, other: &SlowlogRecord) -> bool {
+        self.time == other.time
+            && self.duration == other.duration
+            && self.command == other.command
+            && self.client_socket == other.client_socket
+            && self.client_name == other.client_name
+    }
+}
+
+#[cfg(feature = "json")]
+impl SlowlogRecord {
+    /// The record as it is serialized, as a JSON object that other fields can be added to
+    pub fn to_json_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("SlowlogRecord has only serializable fields")
+    }
+}
+
+// Adds the position of an entry in the reply to its parse error
+fn parse_entry(index: usize, item: &redis::Value) -> redis::RedisResult<SlowlogRecord> {
+    redis::FromRedisValue::from_redis_value(item).map_err(|e: redis::RedisError| {
+        invalid_entry(format!("entry {}, {}", index, e.detail().unwrap_or_default()))
+    })
+}
+
+// One malformed entry doesn't lose the others of the reply, it is logged and skipped
+fn entry(index: usize, item: &redis::Value) -> Option<SlowlogRecord> {
+    parse_entry(index, item)
+        .map_err(|e| log::warn!("Skipping slowlog entry: {}", e))
+        .ok()
+}
+
+/// Entries of a `SLOWLOG GET` reply decoded one at a time, newest first. Entries that aren't
+/// needed are never decoded, and the ones that can't be parsed are logged and skipped.
+pub(crate) fn decode_entries(
+    v: redis::Value,
+) -> redis::RedisResult<impl Iterator<Item = SlowlogRecord>> {
+    let items = match v {
+        redis::Value::Bulk(items) => items,
+        redis::Value::Nil => Vec::new(),
+        v => {
+            return Err(redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Invalid slowlog reply",
+                format!("expected an array, got {}", shape(&v)),
+            )))
+        }
+    };
+    Ok(items.into_iter().enumerate().filter_map(|(index, item)| entry(index, &item)))
+}
+
 fn next_value<T: redis::FromRedisValue>(
     i: &mut std::slice::Iter<redis::Value>,
+    field: &str,
 ) -> redis::RedisResult<T> {
     match i.next() {
-        Some(v) => redis::FromRedisValue::from_redis_value(v),
-        None => Err(redis::RedisError::from((
-            redis::ErrorKind::TypeError,
-            "The field is not found in the response",
-        ))),
+        Some(v) => redis::FromRedisValue::from_redis_value(v)
+            .map_err(|_| invalid_entry(format!("can't parse {} from {}", field, shape(v)))),
+        None => Err(invalid_entry(format!("{} is missing", field))),
     }
 }
 
+// For fields that servers before 4.0 don't return
+fn next_value_or_default<T: redis::FromRedisValue + Default>(
+    i: &mut std::slice::Iter<redis::Value>,
+    field: &str,
+) -> redis::RedisResult<T> {
+    if i.as_slice().is_empty() {
+        Ok(T::default())
+    } else {
+        next_value(i, field)
+    }
+}
+
+// Redis strings are binary safe, bytes that aren't valid UTF-8 are written as \xNN
+// and backslashes are doubled, so the original bytes can be told apart
+fn decode(bytes: &[u8]) -> String {
+    let mut decoded = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        decoded.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for b in chunk.invalid() {
+            decoded.push_str(&format!("\\x{:02x}", b))
+        }
+    }
+    decoded
+}
+
+// Reverses `decode`, a backslash that doesn't start an escape is kept as it is
+fn unescape(arg: &str) -> Vec<u8> {
+    let bytes = arg.as_bytes();
+    let mut raw = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match &bytes[i..] {
+            [b'\\', b'\\', ..] => Some((b'\\', 2)),
+            [b'\\', b'x', hex @ ..] => hex
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .map(|b| (b, 4)),
+            _ => None,
+        };
+        let (b, len) = escaped.unwrap_or((bytes[i], 1));
+        raw.push(b);
+        i += len
+    }
+    raw
+}
+
 impl redis::FromRedisValue for SlowlogRecord {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<SlowlogRecord> {
-        let rows = &mut v.as_sequence().unwrap().iter();
-        Ok(SlowlogRecord {
-            id: next_value(rows)?,
-            time: next_value(rows)?,
-            duration: next_value(rows)?,
-            command: next_value(rows)?,
-            client_socket: next_value(rows)?,
-            client_name: next_value(rows)?,
+        let rows = &mut v
+            .as_sequence()
+            .ok_or_else(|| invalid_entry(format!("expected an array, got {}", shape(v))))?
+            .iter();
+        let id = next_value(rows, "id")?;
+        // Elements after the client name are ignored, newer servers may append more of them
+        let parse = |rows: &mut std::slice::Iter<redis::Value>| {
+            let time = next_value(rows, "time")?;
+            // Redis keeps it in a signed 64 bit integer, negative values would wrap around
+            let duration: i64 = next_value(rows, "duration")?;
+            if duration < 0 {
+                return Err(invalid_entry(format!("negative duration {}", duration)));
+            }
+            Ok(SlowlogRecord {
+                id,
+                time,
+                duration: duration as u64,
+                command: next_value::<Vec<Vec<u8>>>(rows, "command")?
+                    .iter()
+                    .map(|arg| decode(arg))
+                    .collect(),
+                client_socket: decode(&next_value_or_default::<Vec<u8>>(
+                    rows,
+                    "client socket",
+                )?),
+                client_name: decode(&next_value_or_default::<Vec<u8>>(rows, "client name")?),
+            })
+        };
+        parse(rows).map_err(|e: redis::RedisError| {
+            invalid_entry(format!(
+                "id {}, {} in {}",
+                id,
+                e.detail().unwrap_or_default(),
+                shape(v)
+            ))
         })
     }
+
+    // The default implementation drops entries that can't be parsed without a word
+    fn from_redis_values(items: &[redis::Value]) -> redis::RedisResult<Vec<SlowlogRecord>> {
+        Ok(items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| entry(index, item))
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -68,4 +243,178 @@ mod test {
             }
         )
     }
+
+    #[test]
+    fn from_binary() {
+        let val = v::Bulk(vec![
+            v::Int(1),
+            v::Int(2),
+            v::Int(3),
+            v::Bulk(vec![
+                v::Data(b"GET".to_vec()),
+                v::Data(b"key:\xff\x00\xc3".to_vec()),
+                v::Data("ключ".as_bytes().to_vec()),
+                v::Data(b"C:\\xff".to_vec()),
+            ]),
+            v::Data(b"127.0.0.1:10000".to_vec()),
+            v::Data(b"client\x80".to_vec()),
+        ]);
+        let record = SlowlogRecord::from_redis_value(&val).unwrap();
+        assert_eq!(
+            record.command,
+            ["GET", "key:\\xff\0\\xc3", "ключ", "C:\\\\xff"]
+        );
+        assert_eq!(record.client_name, "client\\x80");
+        assert_eq!(
+            record.command_bytes(),
+            [&b"GET"[..], b"key:\xff\x00\xc3", "ключ".as_bytes(), b"C:\\xff"]
+        );
+        assert_eq!(record.payload_bytes(), 3 + 7 + 8 + 6);
+    }
+
+    #[test]
+    fn from_legacy() {
+        let val = v::Bulk(vec![
+            v::Int(1),
+            v::Int(2),
+            v::Int(3),
+            v::Bulk(vec![v::Data("command".as_bytes().to_vec())]),
+        ]);
+        assert_eq!(
+            SlowlogRecord::from_redis_value(&val).unwrap(),
+            SlowlogRecord {
+                id: 1,
+                time: 2,
+                duration: 3,
+                command: vec!["command".to_owned()],
+                ..Default::default()
+            }
+        )
+    }
+    #[test]
+    fn from_extended() {
+        let val = v::Bulk(vec![
+            v::Int(1),
+            v::Int(2),
+            v::Int(3),
+            v::Bulk(vec![v::Data("command".as_bytes().to_vec())]),
+            v::Data("127.0.0.1:10000".as_bytes().to_vec()),
+            v::Data("my cool client".as_bytes().to_vec()),
+            v::Bulk(vec![v::Data("unknown".as_bytes().to_vec()), v::Int(7)]),
+        ]);
+        assert_eq!(
+            SlowlogRecord::from_redis_value(&val).unwrap(),
+            SlowlogRecord {
+                id: 1,
+                time: 2,
+                duration: 3,
+                command: vec!["command".to_owned()],
+                client_socket: "127.0.0.1:10000".to_owned(),
+                client_name: "my cool client".to_owned(),
+            }
+        )
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn to_json() {
+        let record = SlowlogRecord {
+            id: 1,
+            command: vec!["GET".to_owned()],
+            ..Default::default()
+        };
+        let mut value = record.to_json_value();
+        value["region"] = "eu".into();
+        assert_eq!(
+            value.to_string(),
+            r#"{"client_name":"","client_socket":"","command":["GET"],"duration":0,"id":1,"region":"eu","time":0}"#
+        );
+    }
+
+    #[test]
+    fn from_long() {
+        let val = v::Bulk(vec![
+            v::Int(1),
+            v::Int(2),
+            v::Int(i64::MAX),
+            v::Bulk(vec![v::Data("DEBUG".as_bytes().to_vec())]),
+        ]);
+        let record = SlowlogRecord::from_redis_value(&val).unwrap();
+        assert_eq!(record.duration, i64::MAX as u64);
+        let val = v::Bulk(vec![
+            v::Int(1),
+            v::Int(2),
+            v::Int(-1),
+            v::Bulk(vec![v::Data("DEBUG".as_bytes().to_vec())]),
+        ]);
+        assert!(SlowlogRecord::from_redis_value(&val).is_err());
+    }
+
+    #[test]
+    fn from_malformed() {
+        let entries = vec![
+            v::Bulk(vec![
+                v::Int(2),
+                v::Int(2),
+                v::Int(3),
+                v::Bulk(vec![v::Data(b"GET".to_vec())]),
+            ]),
+            v::Bulk(vec![
+                v::Int(1),
+                v::Int(2),
+                v::Data(b"slow".to_vec()),
+                v::Bulk(vec![v::Data(b"GET".to_vec())]),
+            ]),
+        ];
+        let records =
+            Vec::<SlowlogRecord>::from_redis_value(&v::Bulk(entries.clone())).unwrap();
+        assert_eq!(records.iter().map(|r| r.id).collect::<Vec<_>>(), [2]);
+        let decoded = decode_entries(v::Bulk(entries.clone())).unwrap();
+        assert_eq!(decoded.map(|r| r.id).collect::<Vec<_>>(), [2]);
+        let e = parse_entry(1, &entries[1]).unwrap_err();
+        assert_eq!(
+            e.detail(),
+            Some(
+                "entry 1, id 1, can't parse duration from string of 4 bytes \
+                 in array [integer 1, integer 2, string of 4 bytes, array [string of 3 bytes]]"
+            )
+        );
+        let e = SlowlogRecord::from_redis_value(&v::Int(1)).unwrap_err();
+        assert_eq!(e.detail(), Some("expected an array, got integer 1"));
+        let e = SlowlogRecord::from_redis_value(&v::Bulk(vec![v::Int(1)])).unwrap_err();
+        assert_eq!(e.detail(), Some("id 1, time is missing in array [integer 1]"));
+    }
+
+    #[test]
+    fn equality() {
+        let r = SlowlogRecord {
+            id: 1,
+            command: vec!["GET".to_owned(), "k".to_owned()],
+            ..Default::default()
+        };
+        let again = SlowlogRecord {
+            id: 2,
+            ..r.clone()
+        };
+        let slower = SlowlogRecord {
+            duration: 1,
+            ..again.clone()
+        };
+        assert_ne!(r, again);
+        assert!(r.same_content(&again));
+        assert!(!r.same_content(&slower));
+        let records: std::collections::HashSet<_> = vec![r.clone(), again, r].into_iter().collect();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn derived() {
+        let r = SlowlogRecord {
+            command: vec!["MSET".to_owned(), "k1".to_owned(), "value".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(r.arg_count(), 3);
+        assert_eq!(r.payload_bytes(), 11);
+        assert_eq!(SlowlogRecord::default().payload_bytes(), 0);
+    }
 }
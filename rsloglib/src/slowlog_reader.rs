@@ -1,60 +1,337 @@
+use crate::error::{Context, RslogError};
+use crate::filter::Filter;
+use crate::poll::{PollState, RestartCheck};
+use crate::sentinel::Sentinel;
 use crate::slowlog::SlowlogRecord;
+use std::collections::VecDeque;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Returns the password for a new connection, see `RedisConnectionProvider::set_password_callback`
+pub type PasswordCallback = Arc<dyn Fn() -> redis::RedisResult<String> + Send + Sync>;
+
+#[derive(Clone)]
+enum Target {
+    Client(redis::Client),
+    Info(redis::ConnectionInfo),
+    Sentinel(Sentinel),
+}
+
 #[derive(Clone)]
 pub struct RedisConnectionProvider {
-    client: redis::Client,
+    target: Target,
     timeout: u64,
+    read_timeout: Option<Duration>,
+    password_callback: Option<PasswordCallback>,
 }
 
 impl From<(redis::Client, u64)> for RedisConnectionProvider {
     fn from(arg: (redis::Client, u64)) -> RedisConnectionProvider {
         RedisConnectionProvider {
-            client: arg.0,
+            target: Target::Client(arg.0),
+            timeout: arg.1,
+            read_timeout: None,
+            password_callback: None,
+        }
+    }
+}
+
+/// Opens a client for every new connection, so the password can be replaced with
+/// `RedisConnectionProvider::set_password_callback`
+impl From<(redis::ConnectionInfo, u64)> for RedisConnectionProvider {
+    fn from(arg: (redis::ConnectionInfo, u64)) -> RedisConnectionProvider {
+        RedisConnectionProvider {
+            target: Target::Info(arg.0),
             timeout: arg.1,
+            read_timeout: None,
+            password_callback: None,
+        }
+    }
+}
+
+/// Resolves the master on every new connection, so reconnects follow failovers
+impl From<(Sentinel, u64)> for RedisConnectionProvider {
+    fn from(arg: (Sentinel, u64)) -> RedisConnectionProvider {
+        RedisConnectionProvider {
+            target: Target::Sentinel(arg.0),
+            timeout: arg.1,
+            read_timeout: None,
+            password_callback: None,
         }
     }
 }
 
 impl RedisConnectionProvider {
     pub fn get_connection(&self) -> redis::RedisResult<redis::Connection> {
-        self.client
-            .get_connection_with_timeout(Duration::from_secs(self.timeout))
+        let timeout = Duration::from_secs(self.timeout);
+        let con = match &self.target {
+            Target::Client(client) => client.get_connection_with_timeout(timeout),
+            Target::Info(info) => redis::Client::open(self.with_password(info.clone())?)?
+                .get_connection_with_timeout(timeout),
+            Target::Sentinel(sentinel) => {
+                redis::Client::open(self.with_password(sentinel.master_connection_info(timeout)?)?)?
+                    .get_connection_with_timeout(timeout)
+            }
+        }?;
+        con.set_read_timeout(self.read_timeout)?;
+        con.set_write_timeout(self.read_timeout)?;
+        Ok(con)
+    }
+
+    /// Sets how long to wait for the server to answer a command on new connections,
+    /// by default it waits forever
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout
+    }
+
+    /// Calls `callback` before every new connection and authenticates with the password it returns,
+    /// so reconnects keep working when short-lived credentials are rotated. Providers created
+    /// from a `redis::Client` can't replace its password and ignore the callback.
+    pub fn set_password_callback(&mut self, callback: Option<PasswordCallback>) {
+        self.password_callback = callback
+    }
+
+    fn with_password(
+        &self,
+        info: redis::ConnectionInfo,
+    ) -> redis::RedisResult<redis::ConnectionInfo> {
+        match &self.password_callback {
+            Some(callback) => Ok(redis::ConnectionInfo {
+                passwd: Some(callback()?),
+                ..info
+            }),
+            None => Ok(info),
+        }
+    }
+}
+
+/// Errors of transient server states, like a replica that is loading its dataset
+/// or a cluster that is failing over
+pub fn default_reconnect_kinds() -> Vec<redis::ErrorKind> {
+    vec![
+        redis::ErrorKind::BusyLoadingError,
+        redis::ErrorKind::MasterDown,
+        redis::ErrorKind::TryAgain,
+        redis::ErrorKind::ClusterDown,
+    ]
+}
+
+// Lost connections are always re-established
+pub(crate) fn should_reconnect(kinds: &[redis::ErrorKind], e: &RslogError) -> bool {
+    matches!(e, RslogError::Connection { .. }) || kinds.contains(&e.redis_error().kind())
+}
+
+// What made the reader reconnect, in the words of the server for its error codes
+pub(crate) fn reconnect_reason(e: &RslogError) -> String {
+    if let RslogError::Connection { .. } = e {
+        return "a lost connection".to_owned();
+    }
+    match e.redis_error().kind() {
+        redis::ErrorKind::BusyLoadingError => "LOADING".to_owned(),
+        redis::ErrorKind::MasterDown => "MASTERDOWN".to_owned(),
+        redis::ErrorKind::TryAgain => "TRYAGAIN".to_owned(),
+        redis::ErrorKind::ClusterDown => "CLUSTERDOWN".to_owned(),
+        kind => format!("{:?}", kind),
     }
 }
 
 pub struct SlowlogReader {
     connection_provider: RedisConnectionProvider,
     connection: redis::Connection,
+    state: PollState,
+}
+
+/// Where a `SlowlogReader` starts reading
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FollowFrom {
+    /// Returns the records already in slowlog on the first poll
+    Beginning,
+    /// Skips the records that were in slowlog when the reader was built
+    Now,
+}
+
+/// Configures a `SlowlogReader` before connecting, defaults are 128 records per poll,
+/// reading from the oldest record, polling every 5 seconds and checking for restarts on every poll
+pub struct SlowlogReaderBuilder {
+    connection_provider: RedisConnectionProvider,
     last_id: i64,
+    follow_from: FollowFrom,
     length: u32,
-    uptime: u64,
+    filter: Filter,
+    interval: Duration,
+    restart_check_interval: Option<Duration>,
+    command: Vec<String>,
+    reconnect_kinds: Vec<redis::ErrorKind>,
 }
 
-impl std::convert::TryFrom<RedisConnectionProvider> for SlowlogReader {
-    type Error = redis::RedisError;
-    fn try_from(connection_provider: RedisConnectionProvider) -> Result<Self, Self::Error> {
-        let sl_reader = SlowlogReader {
-            connection: connection_provider.get_connection()?,
+impl SlowlogReaderBuilder {
+    pub fn new(connection_provider: RedisConnectionProvider) -> SlowlogReaderBuilder {
+        SlowlogReaderBuilder {
             connection_provider,
             last_id: -1,
+            follow_from: FollowFrom::Beginning,
             length: 128,
-            uptime: 0,
-        };
-        Ok(sl_reader)
+            filter: Filter::default(),
+            interval: Duration::from_secs(5),
+            restart_check_interval: Some(Duration::ZERO),
+            command: default_slowlog_command(),
+            reconnect_kinds: default_reconnect_kinds(),
+        }
+    }
+
+    /// Number of records requested from the server on every poll
+    pub fn length(mut self, length: u32) -> SlowlogReaderBuilder {
+        self.length = length;
+        self
+    }
+
+    /// Only records with a greater id are returned, -1 returns everything in slowlog
+    pub fn last_id(mut self, last_id: i64) -> SlowlogReaderBuilder {
+        self.last_id = last_id;
+        self
+    }
+
+    /// With `FollowFrom::Now` the newest record is read when building the reader and only
+    /// records after it, or after `last_id` if it's greater, are returned
+    pub fn follow_from(mut self, follow_from: FollowFrom) -> SlowlogReaderBuilder {
+        self.follow_from = follow_from;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> SlowlogReaderBuilder {
+        self.filter = filter;
+        self
     }
+
+    /// Time between polls in `SlowlogReader::records`
+    pub fn interval(mut self, interval: Duration) -> SlowlogReaderBuilder {
+        self.interval = interval;
+        self
+    }
+
+    /// Minimal time between reads of the server uptime that detect restarts, zero reads it on every poll
+    /// and `None` never does. Restarts are still noticed when record ids go down, unless more records
+    /// were logged after the restart than before it.
+    pub fn restart_check_interval(mut self, interval: Option<Duration>) -> SlowlogReaderBuilder {
+        self.restart_check_interval = interval;
+        self
+    }
+
+    /// Command and arguments sent instead of `SLOWLOG GET`, for modules and proxies
+    /// that expose the slowlog under another name. The length is appended as the last argument
+    /// and the reply must have the same format as `SLOWLOG GET`.
+    pub fn slowlog_command(mut self, command: Vec<String>) -> SlowlogReaderBuilder {
+        self.command = command;
+        self
+    }
+
+    /// Kinds of errors after which `redis_error_handler` reconnects, in addition to lost
+    /// connections, `default_reconnect_kinds` by default. Errors of other kinds are returned.
+    pub fn reconnect_kinds(mut self, kinds: Vec<redis::ErrorKind>) -> SlowlogReaderBuilder {
+        self.reconnect_kinds = kinds;
+        self
+    }
+
+    pub fn build(self) -> Result<SlowlogReader, RslogError> {
+        let mut connection = self
+            .connection_provider
+            .get_connection()
+            .context("Can't connect to the server")?;
+        let mut last_id = self.last_id;
+        if self.follow_from == FollowFrom::Now {
+            let newest = get_slowlog_with(&mut connection, &self.command, 1)
+                .context("Can't read slowlog")?;
+            if let Some(r) = newest.first() {
+                last_id = last_id.max(r.id as i64)
+            }
+        }
+        Ok(SlowlogReader {
+            connection,
+            connection_provider: self.connection_provider,
+            state: PollState {
+                last_id,
+                length: self.length,
+                command: self.command,
+                filter: self.filter,
+                interval: self.interval,
+                restart_check: RestartCheck::new(self.restart_check_interval),
+                reconnect_kinds: self.reconnect_kinds,
+                ..PollState::default()
+            },
+        })
+    }
+}
+
+impl std::convert::TryFrom<RedisConnectionProvider> for SlowlogReader {
+    type Error = RslogError;
+    fn try_from(connection_provider: RedisConnectionProvider) -> Result<Self, Self::Error> {
+        SlowlogReaderBuilder::new(connection_provider).build()
+    }
+}
+
+/// `SLOWLOG GET`
+pub fn default_slowlog_command() -> Vec<String> {
+    vec!["SLOWLOG".to_owned(), "GET".to_owned()]
+}
+
+// The slowlog command followed by the number of records to return
+pub(crate) fn slowlog_cmd(command: &[String], length: u32) -> redis::Cmd {
+    let mut cmd = redis::Cmd::new();
+    for arg in command {
+        cmd.arg(arg);
+    }
+    cmd.arg(length);
+    cmd
 }
 
 pub fn get_slowlog(
     con: &mut redis::Connection,
     length: u32,
+) -> redis::RedisResult<Vec<SlowlogRecord>> {
+    get_slowlog_with(con, &default_slowlog_command(), length)
+}
+
+/// Same as `get_slowlog`, but sends `command` instead of `SLOWLOG GET`
+pub fn get_slowlog_with(
+    con: &mut redis::Connection,
+    command: &[String],
+    length: u32,
 ) -> redis::RedisResult<Vec<SlowlogRecord>> {
     log::debug!("Executing slowlog query");
-    redis::cmd("SLOWLOG").arg("GET").arg(length).query(con)
+    slowlog_cmd(command, length).query(con)
 }
 
-fn get_uptime(con: &mut redis::Connection) -> redis::RedisResult<u64> {
-    let server_info = redis::cmd("INFO").arg("SERVER").query::<String>(con)?;
+/// Returns up to `count` records, skipping the `offset` newest ones, to page through a large slowlog.
+/// `SLOWLOG GET` has no offset argument, so `offset + count` records are fetched and the newest
+/// `offset` are dropped. Pages shift when new records are added between calls.
+pub fn get_slowlog_page(
+    con: &mut redis::Connection,
+    offset: u32,
+    count: u32,
+) -> redis::RedisResult<Vec<SlowlogRecord>> {
+    let mut records = get_slowlog(con, offset.saturating_add(count))?;
+    Ok(records.split_off((offset as usize).min(records.len())))
+}
+
+pub fn slowlog_len(con: &mut redis::Connection) -> redis::RedisResult<u64> {
+    log::debug!("Executing slowlog length query");
+    redis::cmd("SLOWLOG").arg("LEN").query(con)
+}
+
+/// Clears slowlog and returns the number of removed records
+pub fn reset_slowlog(con: &mut redis::Connection) -> redis::RedisResult<u64> {
+    log::debug!("Executing slowlog reset");
+    let (len, ()) = redis::pipe()
+        .atomic()
+        .cmd("SLOWLOG")
+        .arg("LEN")
+        .cmd("SLOWLOG")
+        .arg("RESET")
+        .query(con)?;
+    Ok(len)
+}
+
+pub(crate) fn parse_uptime(server_info: &str) -> redis::RedisResult<u64> {
     server_info
         .lines()
         .find(|l| l.contains("uptime_in_seconds"))
@@ -78,49 +355,308 @@ fn get_uptime(con: &mut redis::Connection) -> redis::RedisResult<u64> {
         })
 }
 
+/// Part of the `INFO SERVER` answer
+#[derive(Debug, Default, PartialEq)]
+pub struct ServerInfo {
+    pub version: String,
+    /// standalone, cluster or sentinel
+    pub mode: String,
+    pub uptime: u64,
+}
+
+// Empty when the server doesn't report the field
+fn info_field<'a>(info: &'a str, name: &str) -> &'a str {
+    info.lines()
+        .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+        .unwrap_or_default()
+        .trim_end()
+}
+
+pub(crate) fn parse_server_info(info: &str) -> redis::RedisResult<ServerInfo> {
+    Ok(ServerInfo {
+        version: info_field(info, "redis_version").to_owned(),
+        mode: info_field(info, "redis_mode").to_owned(),
+        uptime: parse_uptime(info)?,
+    })
+}
+
+pub fn server_info(con: &mut redis::Connection) -> redis::RedisResult<ServerInfo> {
+    parse_server_info(&redis::cmd("INFO").arg("SERVER").query::<String>(con)?)
+}
+
+/// Server settings that decide what gets into slowlog
+#[derive(Debug, Default, PartialEq)]
+pub struct SlowlogConfig {
+    /// Microseconds a command has to take to be logged, -1 disables slowlog
+    pub log_slower_than: i64,
+    pub max_len: u64,
+}
+
+fn config_value<T: std::str::FromStr>(reply: &[String], name: &str) -> redis::RedisResult<T> {
+    reply.get(1).and_then(|v| v.parse().ok()).ok_or_else(|| {
+        redis::RedisError::from((
+            redis::ErrorKind::TypeError,
+            "Unexpected CONFIG GET answer",
+            name.to_owned(),
+        ))
+    })
+}
+
+pub fn slowlog_config(con: &mut redis::Connection) -> redis::RedisResult<SlowlogConfig> {
+    let (slower_than, max_len): (Vec<String>, Vec<String>) = redis::pipe()
+        .cmd("CONFIG")
+        .arg("GET")
+        .arg("slowlog-log-slower-than")
+        .cmd("CONFIG")
+        .arg("GET")
+        .arg("slowlog-max-len")
+        .query(con)?;
+    Ok(SlowlogConfig {
+        log_slower_than: config_value(&slower_than, "slowlog-log-slower-than")?,
+        max_len: config_value(&max_len, "slowlog-max-len")?,
+    })
+}
+
+/// Changes the given slowlog settings and leaves the others as they are
+pub fn set_slowlog_config(
+    con: &mut redis::Connection,
+    log_slower_than: Option<i64>,
+    max_len: Option<u64>,
+) -> redis::RedisResult<()> {
+    let mut pipe = redis::pipe();
+    if let Some(log_slower_than) = log_slower_than {
+        pipe.cmd("CONFIG")
+            .arg("SET")
+            .arg("slowlog-log-slower-than")
+            .arg(log_slower_than)
+            .ignore();
+    }
+    if let Some(max_len) = max_len {
+        pipe.cmd("CONFIG")
+            .arg("SET")
+            .arg("slowlog-max-len")
+            .arg(max_len)
+            .ignore();
+    }
+    pipe.query(con)
+}
+
 impl SlowlogReader {
-    pub fn get(&mut self) -> redis::RedisResult<Vec<SlowlogRecord>> {
-        self.check_for_restart()?;
-        let sl: Vec<_> = get_slowlog(&mut self.connection, self.length)?;
-        // records in vec are in reverse order
-        if let Some(first_record) = sl.last() {
-            let missing_records = first_record.id as i64 - 1 - self.last_id;
-            if self.last_id > 0 && missing_records > 0 {
-                log::warn!("{} records skiped", missing_records)
-            };
-        };
-        let new_records: Vec<_> = sl
-            .into_iter()
-            .filter(|r| r.id as i64 > self.last_id)
-            .collect();
-        self.last_id = new_records.get(0).map_or(self.last_id, |r| r.id as i64);
-        Ok(new_records)
-    }
-    pub fn update_connection(&mut self) -> Result<(), redis::RedisError> {
-        self.connection = self.connection_provider.get_connection()?;
-        Ok(())
+    pub fn get(&mut self) -> Result<Vec<SlowlogRecord>, RslogError> {
+        self.get_with_length(self.state.length)
+    }
+
+    /// Same as `get`, but requests `length` records on this poll only, like for a deeper
+    /// first read before steady small polls
+    pub fn get_with_length(&mut self, length: u32) -> Result<Vec<SlowlogRecord>, RslogError> {
+        let replies = self.state.pipeline(length).query(&mut self.connection);
+        self.state.take_replies(replies)
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.state.filter = filter
+    }
+
+    /// Sets how many records are requested from the server on every poll.
+    /// Large values on busy servers make each poll slower.
+    pub fn set_length(&mut self, length: u32) {
+        self.state.length = length
+    }
+
+    /// Id of the newest record read so far, -1 if nothing was read yet
+    pub fn last_id(&self) -> i64 {
+        self.state.last_id
+    }
+
+    /// Only records with a greater id are returned from now on, -1 returns everything in slowlog
+    pub fn set_last_id(&mut self, last_id: i64) {
+        self.state.last_id = last_id
     }
 
-    fn check_for_restart(&mut self) -> redis::RedisResult<()> {
-        let uptime = get_uptime(&mut self.connection)?;
-        if uptime < self.uptime {
-            self.last_id = -1;
-            log::info!("Redis server restart detected")
+    /// Sets how long `records` waits between polls that returned nothing new, 5 seconds by default
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.state.interval = interval
+    }
+
+    /// Same as `SlowlogReaderBuilder::restart_check_interval`
+    pub fn set_restart_check_interval(&mut self, interval: Option<Duration>) {
+        self.state.restart_check.set_interval(interval)
+    }
+
+    /// Same as `SlowlogReaderBuilder::slowlog_command`
+    pub fn set_slowlog_command(&mut self, command: Vec<String>) {
+        self.state.command = command
+    }
+
+    /// Same as `SlowlogReaderBuilder::reconnect_kinds`
+    pub fn set_reconnect_kinds(&mut self, kinds: Vec<redis::ErrorKind>) {
+        self.state.reconnect_kinds = kinds
+    }
+
+    /// Whether `redis_error_handler` reconnects after the error
+    pub fn reconnects_on(&self, e: &RslogError) -> bool {
+        self.state.reconnects_on(e)
+    }
+
+    /// Returns an iterator over new records, oldest first, that polls the server as needed.
+    /// It never ends: when there is nothing new it sleeps for the interval and polls again.
+    /// Server restarts and lost connections are handled internally, errors that can't be
+    /// recovered from are yielded and the next call to `next` retries after the interval.
+    pub fn records(&mut self) -> Records<'_> {
+        Records {
+            reader: self,
+            pending: VecDeque::new(),
+            failed: false,
         }
-        self.uptime = uptime;
+    }
+
+    /// Total number of records that fell off the slowlog before they could be read
+    pub fn skipped_records(&self) -> u64 {
+        self.state.skipped_records
+    }
+
+    /// Reads slowlog settings of the server over the reader's connection
+    pub fn slowlog_config(&mut self) -> Result<SlowlogConfig, RslogError> {
+        slowlog_config(&mut self.connection).context("Can't read slowlog config")
+    }
+
+    /// Sends `QUIT` and closes the connection, instead of just dropping it
+    pub fn close(mut self) -> Result<(), RslogError> {
+        log::debug!("Closing connection");
+        redis::cmd("QUIT")
+            .query::<()>(&mut self.connection)
+            .context("Can't close connection")
+    }
+
+    pub fn update_connection(&mut self) -> Result<(), RslogError> {
+        self.connection = self
+            .connection_provider
+            .get_connection()
+            .context("Can't reconnect to the server")?;
         Ok(())
     }
 
-    pub fn redis_error_handler(&mut self, e: redis::RedisError) -> Result<(), redis::RedisError> {
-        if matches!(e.kind(), redis::ErrorKind::IoError) {
-            log::warn!(
-                "Lost connection to redis cluster, trying to establish a new one. Error: {}",
-                e
-            );
-            if let Err(e) = self.update_connection() {
-                return Err(e);
+    /// Reconnects if the connection was lost or the error is of one of the reconnect kinds,
+    /// other errors are returned
+    pub fn redis_error_handler(&mut self, e: RslogError) -> Result<(), RslogError> {
+        if !self.reconnects_on(&e) {
+            return Err(e);
+        }
+        log::warn!("Reconnecting after {}: {}", reconnect_reason(&e), e);
+        self.update_connection()
+    }
+}
+
+/// Iterator returned by `SlowlogReader::records`
+pub struct Records<'a> {
+    reader: &'a mut SlowlogReader,
+    pending: VecDeque<SlowlogRecord>,
+    failed: bool,
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<SlowlogRecord, RslogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(r) = self.pending.pop_front() {
+                return Some(Ok(r));
+            }
+            if self.failed {
+                std::thread::sleep(self.reader.state.interval);
+                self.failed = false
+            }
+            match self.reader.get() {
+                // records come newest first
+                Ok(records) if records.is_empty() => std::thread::sleep(self.reader.state.interval),
+                Ok(records) => self.pending.extend(records.into_iter().rev()),
+                Err(e) => {
+                    if let Err(e) = self.reader.redis_error_handler(e) {
+                        self.failed = true;
+                        return Some(Err(e));
+                    }
+                }
             }
         }
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn server_info_fields() {
+        let info =
+            "# Server\r\nredis_version:7.2.4\r\nredis_mode:cluster\r\nuptime_in_seconds:42\r\n";
+        assert_eq!(
+            parse_server_info(info).unwrap(),
+            ServerInfo {
+                version: "7.2.4".to_owned(),
+                mode: "cluster".to_owned(),
+                uptime: 42
+            }
+        );
+        assert_eq!(
+            parse_server_info("uptime_in_seconds:1\r\n").unwrap().mode,
+            ""
+        );
+    }
+
+    #[test]
+    fn resp3_replies() {
+        // after HELLO 3, INFO answers with a verbatim string and CONFIG GET with a map,
+        // which redis 0.19 can't parse, so RESP3 is never negotiated
+        let info = b"=36\r\ntxt:# Server\r\nuptime_in_seconds:42\r\n\r\n";
+        let config = b"%1\r\n$15\r\nslowlog-max-len\r\n$3\r\n128\r\n";
+        assert!(redis::parse_redis_value(info).is_err());
+        assert!(redis::parse_redis_value(config).is_err());
+        // the same replies in RESP2 are read fine
+        let info = redis::parse_redis_value(b"$32\r\n# Server\r\nuptime_in_seconds:42\r\n\r\n");
+        let info: String = redis::from_redis_value(&info.unwrap()).unwrap();
+        assert_eq!(parse_uptime(&info).unwrap(), 42);
+        let config = redis::parse_redis_value(b"*2\r\n$15\r\nslowlog-max-len\r\n$3\r\n128\r\n");
+        let config: Vec<String> = redis::from_redis_value(&config.unwrap()).unwrap();
+        assert_eq!(
+            config_value::<u64>(&config, "slowlog-max-len").unwrap(),
+            128
+        );
+    }
+
+    #[test]
+    fn reconnect_kinds() {
+        let error = |kind, detail: &str| {
+            RslogError::new(
+                "Can't read slowlog",
+                redis::RedisError::from((
+                    kind,
+                    "An error was signalled by the server",
+                    detail.to_owned(),
+                )),
+            )
+        };
+        let kinds = default_reconnect_kinds();
+        let lost = RslogError::new(
+            "Can't read slowlog",
+            std::io::Error::from(std::io::ErrorKind::ConnectionReset).into(),
+        );
+        assert!(should_reconnect(&[], &lost));
+        assert!(should_reconnect(
+            &kinds,
+            &error(redis::ErrorKind::MasterDown, "MASTERDOWN")
+        ));
+        assert!(!should_reconnect(
+            &[],
+            &error(redis::ErrorKind::MasterDown, "MASTERDOWN")
+        ));
+        assert!(!should_reconnect(
+            &kinds,
+            &error(redis::ErrorKind::ResponseError, "ERR unknown command")
+        ));
+        assert_eq!(reconnect_reason(&lost), "a lost connection");
+        assert_eq!(
+            reconnect_reason(&error(redis::ErrorKind::BusyLoadingError, "LOADING")),
+            "LOADING"
+        );
     }
 }
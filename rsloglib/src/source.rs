@@ -0,0 +1,84 @@
+use crate::error::RslogError;
+use crate::slowlog::SlowlogRecord;
+use crate::slowlog_reader::SlowlogReader;
+#[cfg(feature = "testing")]
+use std::collections::VecDeque;
+
+/// Anything that returns new slowlog records on every call, like `SlowlogReader`,
+/// so code handling records can be tested without a server
+pub trait SlowlogSource {
+    fn get(&mut self) -> Result<Vec<SlowlogRecord>, RslogError>;
+}
+
+impl SlowlogSource for SlowlogReader {
+    fn get(&mut self) -> Result<Vec<SlowlogRecord>, RslogError> {
+        SlowlogReader::get(self)
+    }
+}
+
+/// Returns predetermined results one by one and no records once they run out.
+/// Records of a batch are returned as given, `SlowlogReader` returns newest first.
+#[cfg(feature = "testing")]
+#[derive(Debug, Default)]
+pub struct VecSlowlogSource {
+    results: VecDeque<Result<Vec<SlowlogRecord>, RslogError>>,
+}
+
+#[cfg(feature = "testing")]
+impl VecSlowlogSource {
+    pub fn new(batches: Vec<Vec<SlowlogRecord>>) -> VecSlowlogSource {
+        VecSlowlogSource {
+            results: batches.into_iter().map(Ok).collect(),
+        }
+    }
+
+    /// Adds a batch of records to return after the ones already added
+    pub fn push(&mut self, batch: Vec<SlowlogRecord>) {
+        self.results.push_back(Ok(batch))
+    }
+
+    /// Adds an error to return after the results already added
+    pub fn push_error(&mut self, e: RslogError) {
+        self.results.push_back(Err(e))
+    }
+}
+
+#[cfg(feature = "testing")]
+impl SlowlogSource for VecSlowlogSource {
+    fn get(&mut self) -> Result<Vec<SlowlogRecord>, RslogError> {
+        self.results.pop_front().unwrap_or_else(|| Ok(Vec::new()))
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod test {
+    use super::*;
+
+    fn record(id: u64) -> SlowlogRecord {
+        SlowlogRecord {
+            id,
+            ..Default::default()
+        }
+    }
+
+    fn ids(source: &mut impl SlowlogSource) -> Result<Vec<u64>, RslogError> {
+        Ok(source.get()?.iter().map(|r| r.id).collect())
+    }
+
+    #[test]
+    fn batches() {
+        let mut source = VecSlowlogSource::new(vec![vec![record(2), record(1)]]);
+        source.push_error(RslogError::new(
+            "Can't read slowlog",
+            std::io::Error::from(std::io::ErrorKind::ConnectionReset).into(),
+        ));
+        source.push(vec![record(3)]);
+        assert_eq!(ids(&mut source).unwrap(), [2, 1]);
+        assert!(matches!(
+            ids(&mut source),
+            Err(RslogError::Connection { .. })
+        ));
+        assert_eq!(ids(&mut source).unwrap(), [3]);
+        assert_eq!(ids(&mut source).unwrap(), [0u64; 0]);
+    }
+}
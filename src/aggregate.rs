@@ -0,0 +1,209 @@
+use rsloglib::SlowlogRecord;
+use std::collections::HashMap;
+
+/// Records of a command with --aggregate or of a client with --count-by-client
+#[derive(Debug, PartialEq)]
+pub struct GroupStats {
+    pub key: String,
+    pub count: u64,
+    pub total_duration: u64,
+    pub max_duration: u64,
+}
+
+/// What --count-by-client groups records by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientKey {
+    Ip,
+    Name,
+}
+
+impl ClientKey {
+    pub fn column(self) -> &'static str {
+        match self {
+            ClientKey::Ip => "client_ip",
+            ClientKey::Name => "client_name",
+        }
+    }
+}
+
+fn group(records: &[SlowlogRecord], key: impl Fn(&SlowlogRecord) -> String) -> Vec<GroupStats> {
+    let mut groups: HashMap<String, GroupStats> = HashMap::new();
+    for r in records {
+        let key = key(r);
+        let stats = groups.entry(key.clone()).or_insert_with(|| GroupStats {
+            key,
+            count: 0,
+            total_duration: 0,
+            max_duration: 0,
+        });
+        stats.count += 1;
+        // durations go up to i64::MAX, so a few pathological records would overflow the sum
+        stats.total_duration = stats.total_duration.saturating_add(r.duration);
+        stats.max_duration = stats.max_duration.max(r.duration);
+    }
+    groups.into_values().collect()
+}
+
+/// Groups records by command name, the slowest in total first
+pub fn aggregate(records: &[SlowlogRecord]) -> Vec<GroupStats> {
+    let mut stats = group(records, |r| {
+        r.command
+            .first()
+            .map_or(String::new(), |c| c.to_uppercase())
+    });
+    stats.sort_by(|a, b| {
+        b.total_duration
+            .cmp(&a.total_duration)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    stats
+}
+
+// The port of client_socket changes with every connection, unix sockets have port 0
+fn client_ip(client_socket: &str) -> &str {
+    client_socket
+        .rsplit_once(':')
+        .map_or(client_socket, |(ip, _)| ip)
+}
+
+/// Groups records by client, the one with most records first
+pub fn count_by_client(records: &[SlowlogRecord], by: ClientKey) -> Vec<GroupStats> {
+    let mut stats = group(records, |r| match by {
+        ClientKey::Ip => client_ip(&r.client_socket).to_owned(),
+        // most clients don't set a name
+        ClientKey::Name if r.client_name.is_empty() => "(none)".to_owned(),
+        ClientKey::Name => r.client_name.clone(),
+    });
+    stats.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| b.total_duration.cmp(&a.total_duration))
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    stats
+}
+
+/// `column` is the header of the keys
+pub fn render_table(stats: &[GroupStats], column: &str, header: bool) -> String {
+    let width = stats
+        .iter()
+        .map(|s| s.key.len())
+        .chain(std::iter::once(column.len()))
+        .max()
+        .unwrap();
+    let mut table = String::new();
+    if header {
+        table.push_str(&format!(
+            "{:<width$}  {:>10}  {:>14}  {:>12}\n",
+            column,
+            "count",
+            "total_duration",
+            "max_duration",
+            width = width
+        ))
+    }
+    for s in stats {
+        table.push_str(&format!(
+            "{:<width$}  {:>10}  {:>14}  {:>12}\n",
+            s.key,
+            s.count,
+            s.total_duration,
+            s.max_duration,
+            width = width
+        ))
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(command: &str, duration: u64) -> SlowlogRecord {
+        SlowlogRecord {
+            duration,
+            command: vec![command.to_owned(), "key".to_owned()],
+            ..Default::default()
+        }
+    }
+
+    fn client(socket: &str, name: &str, duration: u64) -> SlowlogRecord {
+        SlowlogRecord {
+            duration,
+            client_socket: socket.to_owned(),
+            client_name: name.to_owned(),
+            ..record("GET", duration)
+        }
+    }
+
+    #[test]
+    fn group() {
+        let stats = aggregate(&[
+            record("get", 10),
+            record("SET", 15),
+            record("GET", 30),
+            record("DEL", 5),
+        ]);
+        assert_eq!(
+            stats,
+            vec![
+                GroupStats {
+                    key: "GET".to_owned(),
+                    count: 2,
+                    total_duration: 40,
+                    max_duration: 30
+                },
+                GroupStats {
+                    key: "SET".to_owned(),
+                    count: 1,
+                    total_duration: 15,
+                    max_duration: 15
+                },
+                GroupStats {
+                    key: "DEL".to_owned(),
+                    count: 1,
+                    total_duration: 5,
+                    max_duration: 5
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn clients() {
+        let records = [
+            client("10.0.0.1:5000", "web", 10),
+            client("10.0.0.2:6000", "", 100),
+            client("10.0.0.1:5001", "web", 20),
+            client("[::1]:7000", "", 5),
+        ];
+        let summary = |stats: Vec<GroupStats>| -> Vec<(String, u64, u64)> {
+            stats
+                .into_iter()
+                .map(|s| (s.key, s.count, s.total_duration))
+                .collect()
+        };
+        assert_eq!(
+            summary(count_by_client(&records, ClientKey::Ip)),
+            [
+                ("10.0.0.1".to_owned(), 2, 30),
+                ("10.0.0.2".to_owned(), 1, 100),
+                ("[::1]".to_owned(), 1, 5)
+            ]
+        );
+        assert_eq!(
+            summary(count_by_client(&records, ClientKey::Name)),
+            [("(none)".to_owned(), 2, 105), ("web".to_owned(), 2, 30)]
+        );
+    }
+
+    #[test]
+    fn huge_durations() {
+        let max = i64::MAX as u64;
+        let stats = aggregate(&[record("GET", max), record("GET", max), record("GET", max)]);
+        assert_eq!(
+            (stats[0].total_duration, stats[0].max_duration),
+            (u64::MAX, max)
+        );
+    }
+}
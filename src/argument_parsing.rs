@@ -1,20 +1,117 @@
-use clap::{App, Arg};
+use crate::aggregate::ClientKey;
+use crate::fields::{parse_fields, Field};
+use crate::template::Template;
+use clap::{App, Arg, ArgSettings};
+use rsloglib::{Filter, FollowFrom, RedactRule, Redaction};
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub enum OutputFormat {
     Text,
     Json,
+    Csv,
+    Tsv,
+    Yaml,
+    Logfmt,
+    Template(Template),
 }
 
 pub struct Config {
     pub hostname: String,
     pub port: u16,
+    pub username: Option<String>,
     pub password: Option<String>,
+    pub password_command: Option<String>,
     pub follow: bool,
-    pub interval: u64,
+    pub interval: Duration,
+    pub start_jitter: bool,
+    pub follow_from: FollowFrom,
+    pub restart_check_interval: Option<Duration>,
     pub verbosity: usize,
     pub quiet: bool,
-    pub timeout: u64,
+    pub json_log: bool,
+    pub status_interval: Option<Duration>,
+    pub heartbeat: Option<Duration>,
+    pub once_empty_exit_code: Option<i32>,
+    pub connect_timeout: u64,
+    pub read_timeout: u64,
+    pub total_timeout: Option<Duration>,
     pub output_format: OutputFormat,
+    pub fields: Option<Vec<Field>>,
+    pub header: bool,
+    pub flush: bool,
+    pub pretty: bool,
+    pub compact_json: bool,
+    pub with_source: bool,
+    pub output_file: Option<PathBuf>,
+    pub tee: bool,
+    pub rotate_size: u64,
+    pub rotate_count: u32,
+    pub syslog: Option<(syslog::Facility, syslog::Severity)>,
+    pub webhook: Option<String>,
+    // brokers and topic
+    pub kafka: Option<(String, String)>,
+    pub metrics_addr: Option<String>,
+    pub serve: Option<String>,
+    pub statsd: Option<String>,
+    pub statsd_prefix: String,
+    pub webhook_batch_size: usize,
+    pub webhook_flush_interval: u64,
+    pub webhook_retries: u32,
+    pub color: bool,
+    pub color_threshold: u64,
+    pub human_duration: bool,
+    pub time_format: Option<String>,
+    pub max_arg_len: usize,
+    pub max_args: usize,
+    pub utc: bool,
+    pub filter: Filter,
+    pub redaction: Option<Redaction>,
+    pub max_records: u64,
+    pub max_rate: Option<f64>,
+    pub dedup: Option<u64>,
+    pub rate_limit_buffer: bool,
+    pub aggregate: bool,
+    pub count_by_client: Option<ClientKey>,
+    pub top: usize,
+    pub stats: bool,
+    pub stats_window: u64,
+    pub reset: bool,
+    pub set_threshold: Option<i64>,
+    pub set_max_len: Option<u64>,
+    pub restore_config: bool,
+    pub len: bool,
+    pub check: bool,
+    pub server_info: bool,
+    pub count: u32,
+    pub slowlog_command: Vec<String>,
+    pub reconnect_kinds: Vec<redis::ErrorKind>,
+    pub retry_auth_errors: bool,
+    pub tail: Option<u32>,
+    pub new_only: bool,
+    pub capture_for: Option<Duration>,
+    pub newest_first: bool,
+    pub watch: bool,
+    pub since_id: Option<u64>,
+    pub checkpoint_file: Option<PathBuf>,
+    pub tls: bool,
+    pub tls_ca_cert: Option<String>,
+    pub insecure: bool,
+    pub socket: Option<String>,
+    pub sentinels: Vec<(String, u16)>,
+    pub master_name: Option<String>,
+    pub db: i64,
+    pub servers: Vec<(String, u16)>,
+    pub cluster: bool,
+    pub topology_interval: u64,
+    pub backoff_base: u64,
+    pub max_backoff: u64,
+    pub max_reconnects: u32,
+    /// Environment variables and config file keys that were ignored for conflicting with
+    /// options set at a higher level, they are logged once logging is set up
+    pub ignored_settings: Vec<String>,
 }
 
 macro_rules! is_parsable {
@@ -26,11 +123,339 @@ macro_rules! is_parsable {
     };
 }
 
-pub fn get_config() -> Result<Config, clap::Error> {
-    let args = App::new("Redis slowlog reader")
+fn validate_time_format(format: &str) -> Result<(), String> {
+    if format == "rfc3339"
+        || !chrono::format::StrftimeItems::new(format).any(|i| i == chrono::format::Item::Error)
+    {
+        Ok(())
+    } else {
+        Err(format!("Invalid time format: {}", format))
+    }
+}
+
+// Parses a number with a `ms`, `s`, `m` or `h` unit, bare numbers are seconds
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split);
+    let invalid = || format!("Invalid duration: {}", duration);
+    let value: u64 = value.parse().map_err(|_| invalid())?;
+    let seconds = |multiplier: u64| {
+        value
+            .checked_mul(multiplier)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid)
+    };
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => seconds(60),
+        "h" => seconds(3600),
+        _ => Err(format!("Unknown unit in duration: {}", duration)),
+    }
+}
+
+// The check is disabled with "off"
+fn parse_restart_check_interval(interval: &str) -> Result<Option<Duration>, String> {
+    match interval {
+        "off" => Ok(None),
+        _ => parse_duration(interval).map(Some),
+    }
+}
+
+// Error codes of the server separated by commas, or none
+fn parse_reconnect_kinds(codes: &str) -> Result<Vec<redis::ErrorKind>, String> {
+    if codes == "none" {
+        return Ok(Vec::new());
+    }
+    codes
+        .split(',')
+        .map(|code| match code.trim().to_uppercase().as_str() {
+            "LOADING" => Ok(redis::ErrorKind::BusyLoadingError),
+            "MASTERDOWN" => Ok(redis::ErrorKind::MasterDown),
+            "TRYAGAIN" => Ok(redis::ErrorKind::TryAgain),
+            "CLUSTERDOWN" => Ok(redis::ErrorKind::ClusterDown),
+            _ => Err(format!(
+                "Unknown error code {:?}, available codes: loading, masterdown, tryagain, clusterdown",
+                code
+            )),
+        })
+        .collect()
+}
+
+// Command and arguments separated by whitespace
+fn parse_slowlog_command(command: &str) -> Result<Vec<String>, String> {
+    let command: Vec<String> = command.split_whitespace().map(|a| a.to_owned()).collect();
+    if command.is_empty() {
+        return Err("Slowlog command can't be empty".to_owned());
+    }
+    Ok(command)
+}
+
+fn parse_threshold(threshold: &str) -> Result<(String, u64), String> {
+    let (command, duration) = threshold.split_once('=').ok_or_else(|| {
+        format!(
+            "Threshold must be in COMMAND=MICROSECONDS form: {}",
+            threshold
+        )
+    })?;
+    let duration = duration
+        .parse()
+        .map_err(|_| format!("Invalid duration in threshold: {}", threshold))?;
+    Ok((command.to_owned(), duration))
+}
+
+fn parse_address(address: &str) -> Result<(String, u16), String> {
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Address must be in HOST:PORT form: {}", address))?;
+    let port = port
+        .parse()
+        .map_err(|_| format!("Invalid port in address: {}", address))?;
+    Ok((host.to_owned(), port))
+}
+
+fn strip_newline(password: &str) -> &str {
+    password
+        .strip_suffix('\n')
+        .map(|p| p.strip_suffix('\r').unwrap_or(p))
+        .unwrap_or(password)
+}
+
+fn read_password_file(path: &str) -> Result<String, clap::Error> {
+    let password = std::fs::read_to_string(path).map_err(|e| {
+        clap::Error::with_description(
+            format!("Can't read password file {}: {}\n", path, e),
+            clap::ErrorKind::Io,
+        )
+    })?;
+    Ok(strip_newline(&password).to_owned())
+}
+
+/// Runs --password-command with sh and returns its output without the trailing newline.
+/// Failures are IO errors, so the connection is retried like when the server is unreachable.
+pub fn run_password_command(command: &str) -> redis::RedisResult<String> {
+    log::debug!("Running password command");
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "Password command failed with {}",
+            output.status
+        ))
+        .into());
+    }
+    let password = String::from_utf8(output.stdout).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Password command printed invalid UTF-8",
+        )
+    })?;
+    Ok(strip_newline(&password).to_owned())
+}
+
+fn syslog_severity(priority: &str) -> syslog::Severity {
+    match priority {
+        "emerg" => syslog::Severity::LOG_EMERG,
+        "alert" => syslog::Severity::LOG_ALERT,
+        "crit" => syslog::Severity::LOG_CRIT,
+        "err" => syslog::Severity::LOG_ERR,
+        "warning" => syslog::Severity::LOG_WARNING,
+        "notice" => syslog::Severity::LOG_NOTICE,
+        "debug" => syslog::Severity::LOG_DEBUG,
+        _ => syslog::Severity::LOG_INFO,
+    }
+}
+
+// Turns a value from the config file or environment into an argument value
+fn config_value(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// Turns an option value into command line arguments, flags take booleans and repeatable options take arrays
+fn option_args(arg: &Arg, value: toml::Value) -> Result<Vec<String>, String> {
+    let option = match (arg.get_long(), arg.get_short()) {
+        (Some(long), _) => format!("--{}", long),
+        (_, Some(short)) => format!("-{}", short),
+        _ => return Ok(Vec::new()),
+    };
+    let unsupported = || format!("unsupported value of {}", arg.get_name());
+    let mut args = Vec::new();
+    match (value, arg.is_set(ArgSettings::TakesValue)) {
+        (toml::Value::Boolean(true), false) => args.push(option),
+        (toml::Value::Boolean(false), false) => {}
+        // like verbosity = 2 for -vv
+        (toml::Value::Integer(n), false) => {
+            args.extend(std::iter::repeat_n(option, n.max(0) as usize))
+        }
+        (toml::Value::Array(values), true) => {
+            for v in values {
+                args.push(format!(
+                    "{}={}",
+                    option,
+                    config_value(&v).ok_or_else(unsupported)?
+                ))
+            }
+        }
+        (v, true) => args.push(format!(
+            "{}={}",
+            option,
+            config_value(&v).ok_or_else(unsupported)?
+        )),
+        _ => {
+            return Err(format!(
+                "{} is a flag and takes true or false",
+                arg.get_name()
+            ))
+        }
+    }
+    Ok(args)
+}
+
+// Turns entries of a TOML config file into command line arguments, keys are long option names
+fn config_file_args(path: &str, app: &App) -> Result<Vec<(String, Vec<String>)>, clap::Error> {
+    let invalid = |message: String| {
+        clap::Error::with_description(
+            format!("Invalid config file {}: {}\n", path, message),
+            clap::ErrorKind::InvalidValue,
+        )
+    };
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        clap::Error::with_description(
+            format!("Can't read config file {}: {}\n", path, e),
+            clap::ErrorKind::Io,
+        )
+    })?;
+    let table: toml::Table = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+    let mut file_args = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let arg = app
+            .get_arguments()
+            .find(|a| a.get_name() == name && name != "config")
+            .ok_or_else(|| invalid(format!("unknown option {}", key)))?;
+        file_args.push((name, option_args(arg, value).map_err(invalid)?))
+    }
+    Ok(file_args)
+}
+
+// Reads RSLOG_<OPTION> variables, like RSLOG_MIN_DURATION for --min-duration.
+// Flags take 1/0, true/false, yes/no or on/off and repeatable options take comma separated lists.
+fn env_args(app: &App) -> Result<Vec<(String, Vec<String>)>, clap::Error> {
+    let mut env_args = Vec::new();
+    for arg in app.get_arguments().filter(|a| a.get_name() != "config") {
+        let var = format!("RSLOG_{}", arg.get_name().to_uppercase().replace('-', "_"));
+        let value = match std::env::var(&var) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let invalid = |message: String| {
+            clap::Error::with_description(
+                format!("Invalid environment variable {}: {}\n", var, message),
+                clap::ErrorKind::InvalidValue,
+            )
+        };
+        let value = if !arg.is_set(ArgSettings::TakesValue) {
+            match value.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => toml::Value::Boolean(true),
+                "" | "0" | "false" | "no" | "off" => toml::Value::Boolean(false),
+                v => toml::Value::Integer(
+                    v.parse()
+                        .map_err(|_| invalid(format!("{} is a flag", arg.get_name())))?,
+                ),
+            }
+        } else if arg.is_set(ArgSettings::MultipleOccurrences) {
+            toml::Value::Array(
+                value
+                    .split(',')
+                    .map(|v| toml::Value::String(v.to_owned()))
+                    .collect(),
+            )
+        } else {
+            toml::Value::String(value)
+        };
+        env_args.push((
+            arg.get_name().to_owned(),
+            option_args(arg, value).map_err(invalid)?,
+        ))
+    }
+    Ok(env_args)
+}
+
+// Options from environment variables and the config file are checked by clap like command line ones.
+// The command line takes precedence over environment variables and they over the config file,
+// an option that conflicts with one from a higher level is ignored.
+// Also returns the settings that were ignored because they conflict with ones merged before them
+fn get_matches() -> Result<(clap::ArgMatches, Vec<String>), clap::Error> {
+    let cli: Vec<String> = std::env::args().collect();
+    let matches = app().try_get_matches_from(&cli)?;
+    let app = app();
+    let mut layers: Vec<_> = env_args(&app)?
+        .into_iter()
+        .map(|(name, args)| {
+            let source = format!(
+                "environment variable RSLOG_{}",
+                name.to_uppercase().replace('-', "_")
+            );
+            (source, name, args)
+        })
+        .collect();
+    let path = matches
+        .value_of("config")
+        .map(|p| p.to_owned())
+        .or_else(|| std::env::var("RSLOG_CONFIG").ok());
+    if let Some(path) = path {
+        layers.extend(
+            config_file_args(&path, &app)?
+                .into_iter()
+                .map(|(name, args)| (format!("{} in config file {}", name, path), name, args)),
+        )
+    }
+    let mut given: HashSet<String> = app
+        .get_arguments()
+        .map(|a| a.get_name())
+        .filter(|name| matches.occurrences_of(name) > 0)
+        .map(|name| name.to_owned())
+        .collect();
+    let mut merged = cli;
+    let mut ignored = Vec::new();
+    for (source, name, args) in layers {
+        if given.contains(&name) {
+            continue;
+        }
+        let conflicts = matches!(
+            app.clone().try_get_matches_from(merged.iter().chain(&args)),
+            Err(e) if e.kind == clap::ErrorKind::ArgumentConflict
+        );
+        if conflicts {
+            ignored.push(source)
+        } else {
+            given.insert(name);
+            merged.extend(args)
+        }
+    }
+    Ok((app.try_get_matches_from(merged)?, ignored))
+}
+
+fn app() -> App<'static> {
+    App::new("Redis slowlog reader")
         .about("Prints redis slowlog to stdout")
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
+        .arg(
+            Arg::from("--config 'TOML file with options, keys are long option names, command line options take precedence'")
+                .takes_value(true),
+        )
         .arg(
             Arg::from("--hostname -h 'Server hostname'")
                 .takes_value(true)
@@ -43,18 +468,53 @@ pub fn get_config() -> Result<Config, clap::Error> {
                 .validator(is_parsable!(u16, "Port mast be a in range 0-65535")),
         )
         .arg(
-            Arg::from("-a --password 'Password to use when connecting to the server'")
+            Arg::from("--username 'Username to use when connecting to the server with ACL'")
                 .takes_value(true)
                 .required(false),
         )
+        .arg(
+            Arg::from("-a --password 'Password to use when connecting to the server, REDIS_PASSWORD environment variable is used if neither this nor --password-file is given'")
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::from("--password-file 'File to read the password from'")
+                .takes_value(true)
+                .conflicts_with("password"),
+        )
+        .arg(
+            Arg::from("--password-command 'Shell command that prints the password, run before every connection so rotated credentials are picked up'")
+                .takes_value(true)
+                .conflicts_with_all(&["password", "password-file"]),
+        )
         .arg(
             Arg::from("-f --follow 'Checks for new records in slowlog and prints if any'")
                 .takes_value(false),
         )
         .arg(
-            Arg::from("--interval -i 'Seconds between trying to get new messages from slowlog'")
+            Arg::from("--watch 'Show the whole slowlog as a table in the terminal and refresh it every interval'")
+                .takes_value(false)
+                .conflicts_with_all(&["follow", "cluster", "aggregate", "count-by-client", "stats", "tail", "output-file"]),
+        )
+        .arg(
+            Arg::from("--interval -i 'Time between trying to get new messages from slowlog, like 500ms or 2s, seconds if there is no unit'")
                 .default_value("5")
-                .validator(is_parsable!(u64, "Interval must be an integer")),
+                .validator(|i| parse_duration(i).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--follow-from 'While following, start with the records already in slowlog with beginning, or only print records logged after rslog started with now. An id from --since-id or --checkpoint-file takes precedence.'")
+                .takes_value(true)
+                .possible_values(&["beginning", "now"])
+                .default_value("beginning"),
+        )
+        .arg(
+            Arg::from("--start-jitter 'While following, wait a random part of --interval before connecting, so instances started together don't poll at the same moment'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--restart-check-interval 'Minimal time between checks of the server uptime that detect restarts, 0 checks on every poll and off never does'")
+                .default_value("0")
+                .validator(|i| parse_restart_check_interval(i).map(|_| ())),
         )
         .arg(
             Arg::new("verbosity")
@@ -65,28 +525,772 @@ pub fn get_config() -> Result<Config, clap::Error> {
         )
         .arg(Arg::from("--quiet -q 'Silence all error messages'").takes_value(false))
         .arg(
-            Arg::from("--timeout 'Timout for redis connection'")
+            Arg::from("--status-interval 'While following, log the number of printed records, reconnects and the time of the last successful poll at info level this often, like 5m'")
+                .takes_value(true)
+                .validator(|i| parse_duration(i).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--heartbeat 'While following, write a heartbeat marked with type heartbeat when no records were written for this long, like 30s, in text, JSON, YAML and logfmt formats'")
+                .takes_value(true)
+                .validator(|i| parse_duration(i).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--log-format 'Format of rslog messages written to stderr, text or a JSON object per line'")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::from("--connect-timeout 'Seconds to wait for connection to the server'")
+                .alias("timeout")
                 .takes_value(true)
                 .validator(is_parsable!(u64, "Timeout must be a positive integer"))
                 .default_value("30"),
         )
-        .arg(Arg::from("--json 'Format output as newline separated JSON'").takes_value(false))
-        .get_matches();
+        .arg(
+            Arg::from("--read-timeout 'Seconds to wait for the server to answer, 0 means forever'")
+                .takes_value(true)
+                .validator(is_parsable!(u64, "Timeout must be a positive integer"))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::from("--total-timeout 'Exit with code 3 if reading the slowlog once, including connecting and retries, takes longer than this, like 10s'")
+                .takes_value(true)
+                .conflicts_with_all(&["follow", "interval", "watch", "new-only", "for", "serve"])
+                .validator(|t| parse_duration(t).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--once-empty-exit-code 'When reading once, exit with this code instead of 0 if no records were found, like 10 for scripts that only act on slow commands'")
+                .takes_value(true)
+                .conflicts_with_all(&["follow", "interval", "watch", "serve", "check", "len", "reset", "server-info"])
+                .validator(is_parsable!(u8, "Exit code must be an integer from 0 to 255")),
+        )
+        .arg(
+            Arg::from("--json 'Format output as newline separated JSON, same as --format json'")
+                .takes_value(false)
+                .conflicts_with("format"),
+        )
+        .arg(
+            Arg::from("--order 'Print records oldest first with asc or newest first with desc, while following this applies to every batch of new records'")
+                .takes_value(true)
+                .possible_values(&["asc", "desc"])
+                .default_value("asc"),
+        )
+        .arg(
+            Arg::from("--format 'Output format'")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv", "tsv", "yaml", "logfmt"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::from("--fields 'Print only these fields in this order, separated by commas, from time, id, duration, client_socket, client_name and command'")
+                .takes_value(true)
+                .conflicts_with_all(&["template", "aggregate", "count-by-client", "stats", "watch"])
+                .validator(|f| parse_fields(f).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--no-header 'Do not print a header row for formats that have one'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from(
+                "--flush 'Flush output after every record instead of after every batch, trades throughput for immediate output'",
+            )
+            .visible_alias("line-buffered")
+            .takes_value(false),
+        )
+        .arg(Arg::from("--pretty 'Indent JSON output'").takes_value(false))
+        .arg(
+            Arg::from("--compact-json 'Leave empty fields like client name out of JSON output'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--with-source 'Add the server address to records when reading a single server, like with --server or --cluster'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--output-file -o 'Append records to this file instead of printing them to stdout'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::from("--tee 'Print records to stdout as well as to the output file'")
+                .takes_value(false)
+                .requires("output-file"),
+        )
+        .arg(
+            Arg::from("--rotate-size 'Move the output file to <file>.1 once it grows over this many bytes, 0 disables rotation'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(u64, "Rotate size must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--rotate-count 'Number of rotated output files to keep'")
+                .takes_value(true)
+                .default_value("5")
+                .validator(is_parsable!(u32, "Rotate count must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--syslog 'Also send every record to the local syslog daemon'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--syslog-facility 'Syslog facility to send records with, like user, daemon or local0'")
+                .takes_value(true)
+                .default_value("user")
+                .validator(|f| f.parse::<syslog::Facility>().map_err(|_| format!("Unknown syslog facility: {}", f))),
+        )
+        .arg(
+            Arg::from("--syslog-priority 'Syslog priority to send records with'")
+                .takes_value(true)
+                .possible_values(&["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"])
+                .default_value("info"),
+        )
+        .arg(
+            Arg::from("--webhook 'Also POST records as JSON arrays to this URL'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::from("--kafka-brokers 'Also produce records as JSON messages to Kafka brokers, like host1:9092,host2:9092, needs the kafka feature'")
+                .takes_value(true)
+                .requires("kafka-topic"),
+        )
+        .arg(
+            Arg::from("--kafka-topic 'Kafka topic to produce records to'")
+                .takes_value(true)
+                .requires("kafka-brokers"),
+        )
+        .arg(
+            Arg::from("--webhook-batch-size 'Maximum number of records in one webhook request'")
+                .takes_value(true)
+                .default_value("100")
+                .validator(|v| match v.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err("Webhook batch size must be a positive integer"),
+                }),
+        )
+        .arg(
+            Arg::from("--webhook-flush-interval 'Milliseconds to wait for more records before sending an incomplete batch'")
+                .takes_value(true)
+                .default_value("1000")
+                .validator(is_parsable!(u64, "Webhook flush interval must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--webhook-retries 'Number of times to retry a failed webhook request before dropping the batch'")
+                .takes_value(true)
+                .default_value("3")
+                .validator(is_parsable!(u32, "Webhook retries must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--metrics-addr 'Serve Prometheus metrics over HTTP on this address, like 127.0.0.1:9121'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::from("--serve 'Follow and stream records to everyone connected to this TCP address, like 127.0.0.1:9122, as JSON objects each preceded by its length as a 4 byte big endian integer'")
+                .takes_value(true)
+                .conflicts_with_all(&["watch", "aggregate", "stats", "tail"]),
+        )
+        .arg(
+            Arg::from("--statsd 'Send a timing and a counter for every record to the StatsD server at HOST:PORT while following'")
+                .takes_value(true)
+                .validator(parse_address),
+        )
+        .arg(
+            Arg::from("--statsd-prefix 'Prefix of StatsD metric names'")
+                .takes_value(true)
+                .default_value("rslog"),
+        )
+        .arg(
+            Arg::from("--color 'Colorize text output, auto colorizes only when writing to a terminal and NO_COLOR is not set'")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::from("--color-threshold 'Duration in microseconds above which it is highlighted'")
+                .takes_value(true)
+                .default_value("10000")
+                .validator(is_parsable!(u64, "Color threshold must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--template 'Format records with a template like \"{time} {duration}us {command}\", available fields: time, id, duration, client_socket, client_name, command, server, count'")
+                .takes_value(true)
+                .conflicts_with_all(&["format", "json"])
+                .validator(|t| Template::parse(t).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--human-duration 'Print durations in text output as 1.2ms or 3.4s instead of microseconds'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--time-format 'Format record time with a strftime pattern or as rfc3339, applies to all formats except json and yaml'")
+                .takes_value(true)
+                .validator(validate_time_format),
+        )
+        .arg(
+            Arg::from("--utc 'Print formatted time in UTC instead of local time'")
+                .takes_value(false)
+                .requires("time-format"),
+        )
+        .arg(
+            Arg::from("--max-arg-len 'Shorten command arguments longer than this many characters, 0 means unlimited'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(usize, "Max argument length must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--max-args 'Print only this many words of a command, counting its name, followed by the number of the rest, 0 means unlimited'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(usize, "Max arguments must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--min-duration 'Skip records faster than this amount of microseconds'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(u64, "Minimal duration must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--threshold 'Minimal duration in microseconds for one command as COMMAND=MICROSECONDS, in place of --min-duration, can be repeated'")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|t| parse_threshold(t).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--command 'Show only records of this command, can be repeated'")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::from("--exclude-command 'Skip records of this command, can be repeated'")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::from("--command-regex 'Show only records whose command with arguments matches this regular expression'")
+                .takes_value(true)
+                .validator(regex::Regex::new),
+        )
+        .arg(
+            Arg::from("--client 'Show only records from this client address, matches any port if none is given'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::from("--client-name 'Show only records from clients with this name'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::from("--redact 'Replace secret command arguments with ***, arguments of AUTH are always hidden'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--redact-rule 'Also hide an argument as COMMAND:POSITION, with positions from 1 or * for all arguments, can be repeated'")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|r| r.parse::<RedactRule>().map(|_| ())),
+        )
+        .arg(
+            Arg::from("--max-records 'Exit after printing this many records in follow mode, 0 means unlimited'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(u64, "Max records must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--max-rate 'Print at most this many records per second while following'")
+                .takes_value(true)
+                .validator(|r| match r.parse::<f64>() {
+                    Ok(r) if r > 0.0 && r.is_finite() => Ok(()),
+                    _ => Err("Max rate must be a positive number".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::from("--rate-limit-mode 'What to do with records over --max-rate, drop them or buffer them and print later'")
+                .takes_value(true)
+                .possible_values(&["drop", "buffer"])
+                .default_value("drop"),
+        )
+        .arg(
+            Arg::from("--dedup 'Print identical commands logged within --dedup-window once, with the number of them and the longest duration'")
+                .takes_value(false)
+                .conflicts_with_all(&["aggregate", "count-by-client", "stats", "watch"]),
+        )
+        .arg(
+            Arg::from("--dedup-window 'Seconds after the first of identical commands in which the others are collapsed into it'")
+                .takes_value(true)
+                .default_value("60")
+                .validator(|w| match w.parse::<u64>() {
+                    Ok(w) if w > 0 => Ok(()),
+                    _ => Err("Dedup window must be a positive integer".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::from("--new-only 'Print only records logged after rslog started, waiting for --for or until interrupted with Ctrl-C'")
+                .takes_value(false)
+                .conflicts_with_all(&["follow", "interval", "watch", "tail"]),
+        )
+        .arg(
+            Arg::from("--for 'How long to wait for new records with --new-only, like 30s, implies --new-only'")
+                .takes_value(true)
+                .conflicts_with_all(&["follow", "interval", "watch", "tail"])
+                .validator(|d| parse_duration(d).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--tail 'Print only this many newest records, up to slowlog-max-len of the server'")
+                .takes_value(true)
+                .conflicts_with_all(&["follow", "interval", "aggregate", "count-by-client", "stats"])
+                .validator(is_parsable!(u32, "Tail must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--aggregate 'Print count, total and maximum duration of every command instead of records'")
+                .takes_value(false)
+                .conflicts_with_all(&["follow", "interval"]),
+        )
+        .arg(
+            Arg::from("--count-by-client 'Print count, total and maximum duration of the records of every client ip or name instead of records, most records first'")
+                .takes_value(true)
+                .possible_values(&["ip", "name"])
+                .conflicts_with_all(&["follow", "interval", "aggregate"]),
+        )
+        .arg(
+            Arg::from("--top 'Show only this many of the slowest commands with --aggregate or clients with --count-by-client, 0 means all'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(usize, "Top must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--stats 'Print count, min, max, mean and p50/p95/p99 of durations instead of records, text or json'")
+                .takes_value(false)
+                .conflicts_with_all(&["aggregate", "count-by-client"]),
+        )
+        .arg(
+            Arg::from("--stats-window 'Seconds of records to compute --stats over while following'")
+                .takes_value(true)
+                .default_value("60")
+                .validator(is_parsable!(u64, "Stats window must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--reset 'Clear slowlog on the server and exit'")
+                .takes_value(false)
+                .requires("yes"),
+        )
+        .arg(Arg::from("--yes 'Confirm destructive operations like --reset'").takes_value(false))
+        .arg(
+            Arg::from("--set-threshold 'Set slowlog-log-slower-than on the server to this many microseconds before reading, -1 disables slowlog'")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .requires("yes")
+                .validator(|t| match t.parse::<i64>() {
+                    Ok(t) if t >= -1 => Ok(()),
+                    _ => Err("Threshold must be an integer, -1 or greater"),
+                }),
+        )
+        .arg(
+            Arg::from("--set-max-len 'Set slowlog-max-len on the server before reading'")
+                .takes_value(true)
+                .requires("yes")
+                .validator(is_parsable!(u64, "Max length must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--restore-config 'Put back the slowlog settings changed with --set-threshold and --set-max-len on exit'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--len 'Print the number of records in slowlog and exit'")
+                .takes_value(false)
+                .conflicts_with("reset"),
+        )
+        .arg(
+            Arg::from("--server-info 'Print the version of rslog and the version, mode and uptime of the server, then exit'")
+                .takes_value(false)
+                .conflicts_with_all(&["check", "len", "reset"]),
+        )
+        .arg(
+            Arg::from("--check 'Check that the server is reachable and slowlog can be read, then exit with a non zero code on failure'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--count -n 'Number of records to request from slowlog, large values can be slow on busy servers'")
+                .takes_value(true)
+                .default_value("128")
+                .validator(is_parsable!(u32, "Count must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--slowlog-command 'Command sent instead of SLOWLOG GET, for modules and proxies with their own slowlog, the count is appended to it'")
+                .takes_value(true)
+                .default_value("SLOWLOG GET")
+                .validator(|c| parse_slowlog_command(c).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--since-id 'Only read records with a greater id, to resume from a known record'")
+                .takes_value(true)
+                .validator(is_parsable!(u64, "Since id must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--checkpoint-file 'Save the id of the last record read while following and resume from it on start'")
+                .takes_value(true)
+                .conflicts_with_all(&["server", "cluster"]),
+        )
+        .arg(Arg::from("--tls 'Use TLS to connect to the server'").takes_value(false))
+        .arg(
+            Arg::from("--tls-ca-cert 'Path to a CA certificate bundle used to verify the server in place of the system certificates, only where TLS uses OpenSSL, like on Linux, not on macOS or Windows'")
+                .takes_value(true)
+                .requires("tls"),
+        )
+        .arg(
+            Arg::from("--insecure 'Skip verification of the server certificate'")
+                .takes_value(false)
+                .requires("tls"),
+        )
+        .arg(Arg::from("--resp3 'Talk RESP3 to the server, not supported by the redis client rslog is built on yet'").takes_value(false))
+        .arg(
+            Arg::from("--socket -s 'Path to the server unix socket'")
+                .takes_value(true)
+                .conflicts_with_all(&["hostname", "port", "tls"]),
+        )
+        .arg(
+            Arg::from("--sentinel 'Sentinel address as HOST:PORT to discover the master from, can be repeated'")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(parse_address)
+                .requires("master-name")
+                .conflicts_with_all(&["hostname", "port", "socket"]),
+        )
+        .arg(
+            Arg::from("--master-name 'Name of the master monitored by sentinels'")
+                .takes_value(true)
+                .requires("sentinel"),
+        )
+        .arg(
+            Arg::from("--db -d 'Database number to select'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(u32, "Database must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--server 'Server address as HOST:PORT, can be repeated to read from several servers at once'")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(parse_address)
+                .conflicts_with_all(&["hostname", "port", "socket", "sentinel", "len", "reset"]),
+        )
+        .arg(
+            Arg::from("--cluster 'Read slowlog from all master nodes of the cluster the server belongs to'")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "server",
+                    "socket",
+                    "sentinel",
+                    "len",
+                    "reset",
+                    "set-threshold",
+                    "set-max-len",
+                ]),
+        )
+        .arg(
+            Arg::from("--topology-interval 'Seconds between checks for nodes joining or leaving the cluster'")
+                .takes_value(true)
+                .default_value("60")
+                .validator(is_parsable!(u64, "Topology interval must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--backoff-base 'Seconds to wait before the first reconnection attempt, doubled on every next one'")
+                .takes_value(true)
+                .default_value("1")
+                .validator(is_parsable!(u64, "Backoff base must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--max-backoff 'Maximum seconds to wait between reconnection attempts'")
+                .takes_value(true)
+                .default_value("60")
+                .validator(is_parsable!(u64, "Max backoff must be a positive integer")),
+        )
+        .arg(
+            Arg::from("--reconnect-on 'Server errors after which the connection is re-established while following, in addition to lost connections, comma separated loading, masterdown, tryagain and clusterdown, or none'")
+                .takes_value(true)
+                .default_value("loading,masterdown,tryagain,clusterdown")
+                .validator(|c| parse_reconnect_kinds(c).map(|_| ())),
+        )
+        .arg(
+            Arg::from("--retry-auth-errors 'While following, log rejected credentials and missing permissions and retry, like during an ACL reload, instead of exiting'")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::from("--max-reconnects 'Exit after this many consecutive connection failures while following, 0 retries forever'")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable!(u32, "Max reconnects must be a positive integer")),
+        )
+}
 
+pub fn get_config() -> Result<Config, clap::Error> {
+    let (args, ignored_settings) = get_matches()?;
+    if args.is_present("resp3") {
+        return Err(clap::Error::with_description(
+            "--resp3 isn't supported, the redis client rslog is built on only parses RESP2 replies\n"
+                .to_owned(),
+            clap::ErrorKind::InvalidValue,
+        ));
+    }
+
+    let password_command = args.value_of("password-command").map(|c| c.to_owned());
+    let password = match (args.value_of("password"), args.value_of("password-file")) {
+        (Some(password), _) => Some(password.to_owned()),
+        (None, Some(path)) => Some(read_password_file(path)?),
+        (None, None) if password_command.is_some() => None,
+        (None, None) => std::env::var("REDIS_PASSWORD").ok(),
+    };
+    let checkpoint_file = args.value_of("checkpoint-file").map(PathBuf::from);
+    let since_id = match (args.value_of("since-id"), &checkpoint_file) {
+        (Some(id), _) => Some(id.parse().unwrap()),
+        (None, Some(path)) => crate::checkpoint::load(path).map_err(|e| {
+            clap::Error::with_description(
+                format!("Can't read checkpoint file {}: {}\n", path.display(), e),
+                clap::ErrorKind::Io,
+            )
+        })?,
+        (None, None) => None,
+    };
     let config = Config {
         hostname: args.value_of("hostname").unwrap().to_owned(),
         port: args.value_of("port").unwrap().parse().unwrap(),
-        password: args.value_of("password").map(|p| p.to_owned()),
-        interval: args.value_of("interval").unwrap().parse().unwrap(),
-        follow: args.is_present("follow") || args.occurrences_of("interval") > 0,
+        username: args.value_of("username").map(|u| u.to_owned()),
+        password,
+        password_command,
+        interval: parse_duration(args.value_of("interval").unwrap()).unwrap(),
+        start_jitter: args.is_present("start-jitter"),
+        follow_from: match args.value_of("follow-from").unwrap() {
+            "now" => FollowFrom::Now,
+            _ => FollowFrom::Beginning,
+        },
+        restart_check_interval: parse_restart_check_interval(
+            args.value_of("restart-check-interval").unwrap(),
+        )
+        .unwrap(),
+        // the interval is also the refresh period of --watch
+        follow: (args.is_present("follow")
+            || args.occurrences_of("interval") > 0
+            || args.is_present("serve"))
+            && !args.is_present("watch"),
+        watch: args.is_present("watch"),
         verbosity: args.occurrences_of("verbosity") as usize,
         quiet: args.is_present("quiet"),
-        timeout: args.value_of("timeout").unwrap().parse().unwrap(),
+        json_log: args.value_of("log-format").unwrap() == "json",
+        status_interval: args
+            .value_of("status-interval")
+            .map(|i| parse_duration(i).unwrap()),
+        once_empty_exit_code: args
+            .value_of("once-empty-exit-code")
+            .map(|c| c.parse().unwrap()),
+        heartbeat: args
+            .value_of("heartbeat")
+            .map(|i| parse_duration(i).unwrap()),
+        connect_timeout: args.value_of("connect-timeout").unwrap().parse().unwrap(),
+        read_timeout: args.value_of("read-timeout").unwrap().parse().unwrap(),
+        total_timeout: args
+            .value_of("total-timeout")
+            .map(|t| parse_duration(t).unwrap()),
+        fields: args.value_of("fields").map(|f| parse_fields(f).unwrap()),
         output_format: if args.is_present("json") {
             OutputFormat::Json
+        } else if let Some(t) = args.value_of("template") {
+            OutputFormat::Template(Template::parse(t).unwrap())
+        } else {
+            match args.value_of("format").unwrap() {
+                "json" => OutputFormat::Json,
+                "csv" => OutputFormat::Csv,
+                "tsv" => OutputFormat::Tsv,
+                "yaml" => OutputFormat::Yaml,
+                "logfmt" => OutputFormat::Logfmt,
+                _ => OutputFormat::Text,
+            }
+        },
+        header: !args.is_present("no-header"),
+        flush: args.is_present("flush"),
+        pretty: args.is_present("pretty"),
+        compact_json: args.is_present("compact-json"),
+        with_source: args.is_present("with-source"),
+        output_file: args.value_of("output-file").map(PathBuf::from),
+        tee: args.is_present("tee"),
+        rotate_size: args.value_of("rotate-size").unwrap().parse().unwrap(),
+        rotate_count: args.value_of("rotate-count").unwrap().parse().unwrap(),
+        webhook: args.value_of("webhook").map(|u| u.to_owned()),
+        kafka: args.value_of("kafka-brokers").map(|brokers| {
+            (
+                brokers.to_owned(),
+                args.value_of("kafka-topic").unwrap().to_owned(),
+            )
+        }),
+        metrics_addr: args.value_of("metrics-addr").map(|a| a.to_owned()),
+        serve: args.value_of("serve").map(|a| a.to_owned()),
+        statsd: args.value_of("statsd").map(|a| a.to_owned()),
+        statsd_prefix: args.value_of("statsd-prefix").unwrap().to_owned(),
+        webhook_batch_size: args
+            .value_of("webhook-batch-size")
+            .unwrap()
+            .parse()
+            .unwrap(),
+        webhook_flush_interval: args
+            .value_of("webhook-flush-interval")
+            .unwrap()
+            .parse()
+            .unwrap(),
+        webhook_retries: args.value_of("webhook-retries").unwrap().parse().unwrap(),
+        syslog: if args.is_present("syslog") {
+            Some((
+                args.value_of("syslog-facility").unwrap().parse().unwrap(),
+                syslog_severity(args.value_of("syslog-priority").unwrap()),
+            ))
         } else {
-            OutputFormat::Text
+            None
         },
+        color: match args.value_of("color").unwrap() {
+            "always" => true,
+            "never" => false,
+            _ => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && !args.is_present("output-file")
+                    && std::io::stdout().is_terminal()
+            }
+        },
+        color_threshold: args.value_of("color-threshold").unwrap().parse().unwrap(),
+        human_duration: args.is_present("human-duration"),
+        time_format: args.value_of("time-format").map(|f| f.to_owned()),
+        max_arg_len: args.value_of("max-arg-len").unwrap().parse().unwrap(),
+        max_args: args.value_of("max-args").unwrap().parse().unwrap(),
+        utc: args.is_present("utc"),
+        filter: Filter {
+            min_duration: args.value_of("min-duration").unwrap().parse().unwrap(),
+            thresholds: args.values_of("threshold").map_or(Vec::new(), |v| {
+                v.map(|t| parse_threshold(t).unwrap()).collect()
+            }),
+            commands: args
+                .values_of("command")
+                .map_or(Vec::new(), |v| v.map(|c| c.to_owned()).collect()),
+            exclude_commands: args
+                .values_of("exclude-command")
+                .map_or(Vec::new(), |v| v.map(|c| c.to_owned()).collect()),
+            command_regex: args
+                .value_of("command-regex")
+                .map(|p| regex::Regex::new(p).unwrap()),
+            client: args.value_of("client").map(|c| c.to_owned()),
+            client_name: args.value_of("client-name").map(|n| n.to_owned()),
+        },
+        redaction: if args.is_present("redact") || args.is_present("redact-rule") {
+            let mut rules = Redaction::default_rules();
+            if let Some(r) = args.values_of("redact-rule") {
+                rules.extend(r.map(|r| r.parse::<RedactRule>().unwrap()))
+            }
+            Some(Redaction { rules })
+        } else {
+            None
+        },
+        max_records: args.value_of("max-records").unwrap().parse().unwrap(),
+        max_rate: args.value_of("max-rate").map(|r| r.parse().unwrap()),
+        dedup: args
+            .is_present("dedup")
+            .then(|| args.value_of("dedup-window").unwrap().parse().unwrap()),
+        rate_limit_buffer: args.value_of("rate-limit-mode").unwrap() == "buffer",
+        aggregate: args.is_present("aggregate"),
+        count_by_client: args.value_of("count-by-client").map(|by| match by {
+            "name" => ClientKey::Name,
+            _ => ClientKey::Ip,
+        }),
+        top: args.value_of("top").unwrap().parse().unwrap(),
+        stats: args.is_present("stats"),
+        stats_window: args.value_of("stats-window").unwrap().parse().unwrap(),
+        reset: args.is_present("reset"),
+        set_threshold: args.value_of("set-threshold").map(|t| t.parse().unwrap()),
+        set_max_len: args.value_of("set-max-len").map(|l| l.parse().unwrap()),
+        restore_config: args.is_present("restore-config"),
+        len: args.is_present("len"),
+        check: args.is_present("check"),
+        server_info: args.is_present("server-info"),
+        count: args.value_of("count").unwrap().parse().unwrap(),
+        retry_auth_errors: args.is_present("retry-auth-errors"),
+        reconnect_kinds: parse_reconnect_kinds(args.value_of("reconnect-on").unwrap()).unwrap(),
+        slowlog_command: parse_slowlog_command(args.value_of("slowlog-command").unwrap()).unwrap(),
+        new_only: args.is_present("new-only") || args.is_present("for"),
+        capture_for: args.value_of("for").map(|d| parse_duration(d).unwrap()),
+        tail: args.value_of("tail").map(|t| t.parse().unwrap()),
+        newest_first: args.value_of("order").unwrap() == "desc",
+        since_id,
+        checkpoint_file,
+        tls: args.is_present("tls"),
+        tls_ca_cert: args.value_of("tls-ca-cert").map(|p| p.to_owned()),
+        insecure: args.is_present("insecure"),
+        socket: args.value_of("socket").map(|p| p.to_owned()),
+        sentinels: args.values_of("sentinel").map_or(Vec::new(), |v| {
+            v.map(|s| parse_address(s).unwrap()).collect()
+        }),
+        master_name: args.value_of("master-name").map(|n| n.to_owned()),
+        db: args.value_of("db").unwrap().parse().unwrap(),
+        servers: args.values_of("server").map_or(Vec::new(), |v| {
+            v.map(|s| parse_address(s).unwrap()).collect()
+        }),
+        cluster: args.is_present("cluster"),
+        topology_interval: args.value_of("topology-interval").unwrap().parse().unwrap(),
+        backoff_base: args.value_of("backoff-base").unwrap().parse().unwrap(),
+        max_backoff: args.value_of("max-backoff").unwrap().parse().unwrap(),
+        max_reconnects: args.value_of("max-reconnects").unwrap().parse().unwrap(),
+        ignored_settings,
     };
+    // a heartbeat line would break the columns of these formats
+    if config.heartbeat.is_some()
+        && matches!(
+            config.output_format,
+            OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Template(_)
+        )
+    {
+        return Err(clap::Error::with_description(
+            "--heartbeat can't be used with csv, tsv and template output\n".to_owned(),
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
     Ok(config)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("-1").is_err());
+        assert_eq!(
+            parse_duration("9999999999999999h"),
+            Err("Invalid duration: 9999999999999999h".to_owned())
+        );
+        assert_eq!(parse_restart_check_interval("off"), Ok(None));
+        assert_eq!(
+            parse_restart_check_interval("30s"),
+            Ok(Some(Duration::from_secs(30)))
+        );
+    }
+
+    #[test]
+    fn reconnect_kinds() {
+        assert_eq!(
+            parse_reconnect_kinds("loading, TRYAGAIN"),
+            Ok(vec![
+                redis::ErrorKind::BusyLoadingError,
+                redis::ErrorKind::TryAgain
+            ])
+        );
+        assert_eq!(parse_reconnect_kinds("none"), Ok(Vec::new()));
+        assert!(parse_reconnect_kinds("moved").is_err());
+    }
+
+    #[test]
+    fn slowlog_command() {
+        assert_eq!(
+            parse_slowlog_command(" PROXY.SLOWLOG  GET "),
+            Ok(vec!["PROXY.SLOWLOG".to_owned(), "GET".to_owned()])
+        );
+        assert!(parse_slowlog_command(" ").is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use std::io;
+use std::path::Path;
+
+/// Returns the saved id, or nothing if there is no checkpoint yet
+pub fn load(path: &Path) -> io::Result<Option<u64>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it,
+/// so a crash never leaves a partially written checkpoint
+pub fn save(path: &Path, id: u64) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, format!("{}\n", id))?;
+    std::fs::rename(&tmp, path)
+}
@@ -0,0 +1,120 @@
+use rsloglib::SlowlogRecord;
+
+/// The first of identical commands logged within the window, with the longest of their durations
+#[derive(Debug, PartialEq)]
+pub struct Duplicates {
+    pub server: Option<String>,
+    pub record: SlowlogRecord,
+    pub count: u64,
+}
+
+/// Collapses records of a server with the same command and arguments
+/// that were logged within `window` seconds after the first of them
+pub struct Dedup {
+    window: u64,
+    groups: Vec<Duplicates>,
+}
+
+// Command names are case insensitive, arguments are not
+fn same_command(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).enumerate().all(|(i, (a, b))| {
+            if i == 0 {
+                a.eq_ignore_ascii_case(b)
+            } else {
+                a == b
+            }
+        })
+}
+
+impl Dedup {
+    pub fn new(window: u64) -> Dedup {
+        Dedup {
+            window,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Adds records oldest first and returns the groups whose window ended before one of them,
+    /// in the order they started
+    pub fn add(&mut self, server: Option<&str>, records: Vec<SlowlogRecord>) -> Vec<Duplicates> {
+        let mut done = Vec::new();
+        for r in records {
+            done.extend(self.expire(r.time));
+            let group = self.groups.iter_mut().find(|g| {
+                g.server.as_deref() == server && same_command(&g.record.command, &r.command)
+            });
+            match group {
+                Some(group) => {
+                    group.count += 1;
+                    group.record.duration = group.record.duration.max(r.duration)
+                }
+                None => self.groups.push(Duplicates {
+                    server: server.map(|s| s.to_owned()),
+                    record: r,
+                    count: 1,
+                }),
+            }
+        }
+        done
+    }
+
+    /// Returns the groups whose window ended by `now`, a unix timestamp
+    pub fn expire(&mut self, now: u64) -> Vec<Duplicates> {
+        let window = self.window;
+        let (done, open) = std::mem::take(&mut self.groups)
+            .into_iter()
+            .partition(|g| g.record.time + window <= now);
+        self.groups = open;
+        done
+    }
+
+    /// Returns all groups, finished or not
+    pub fn finish(&mut self) -> Vec<Duplicates> {
+        std::mem::take(&mut self.groups)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(id: u64, time: u64, duration: u64, command: &[&str]) -> SlowlogRecord {
+        SlowlogRecord {
+            id,
+            time,
+            duration,
+            command: command.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn summary(groups: Vec<Duplicates>) -> Vec<(u64, u64, u64)> {
+        groups
+            .into_iter()
+            .map(|g| (g.record.id, g.record.duration, g.count))
+            .collect()
+    }
+
+    #[test]
+    fn collapse() {
+        let mut dedup = Dedup::new(10);
+        let done = dedup.add(
+            None,
+            vec![
+                record(1, 100, 20, &["GET", "a"]),
+                record(2, 101, 50, &["get", "a"]),
+                record(3, 102, 30, &["GET", "b"]),
+                record(4, 109, 10, &["GET", "a"]),
+                record(5, 110, 40, &["GET", "a"]),
+            ],
+        );
+        assert_eq!(summary(done), [(1, 50, 3)]);
+        assert_eq!(
+            summary(dedup.add(Some("other"), vec![record(6, 111, 5, &["GET", "a"])])),
+            []
+        );
+        assert_eq!(summary(dedup.expire(112)), [(3, 30, 1)]);
+        assert_eq!(summary(dedup.finish()), [(5, 40, 1), (6, 5, 1)]);
+    }
+}
@@ -0,0 +1,72 @@
+/// Record fields that can be chosen with --fields
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Time,
+    Id,
+    Duration,
+    ClientSocket,
+    ClientName,
+    Command,
+}
+
+pub const ALL: [Field; 6] = [
+    Field::Time,
+    Field::Id,
+    Field::Duration,
+    Field::ClientSocket,
+    Field::ClientName,
+    Field::Command,
+];
+
+impl Field {
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Time => "time",
+            Field::Id => "id",
+            Field::Duration => "duration",
+            Field::ClientSocket => "client_socket",
+            Field::ClientName => "client_name",
+            Field::Command => "command",
+        }
+    }
+}
+
+// Fields in the given order, separated by commas
+pub fn parse_fields(list: &str) -> Result<Vec<Field>, String> {
+    let mut fields = Vec::new();
+    for name in list.split(',').map(|n| n.trim()) {
+        let field = ALL
+            .iter()
+            .copied()
+            .find(|f| f.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = ALL.iter().map(|f| f.name()).collect();
+                format!(
+                    "Unknown field {:?}, available fields: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        if fields.contains(&field) {
+            return Err(format!("Field {} is given twice", name));
+        }
+        fields.push(field)
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_fields("time, duration,command"),
+            Ok(vec![Field::Time, Field::Duration, Field::Command])
+        );
+        assert!(parse_fields("time,user").is_err());
+        assert!(parse_fields("id,id").is_err());
+        assert!(parse_fields("").is_err());
+    }
+}
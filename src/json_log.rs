@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::io::Write;
+
+// One line of --log-format json
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+/// Writes rslog's own messages to stderr as JSON objects, one per line
+pub struct JsonLogger {
+    level: log::LevelFilter,
+}
+
+// Same levels as stderrlog uses for the number of -v
+fn level(verbosity: usize, quiet: bool) -> log::LevelFilter {
+    if quiet {
+        return log::LevelFilter::Off;
+    }
+    match verbosity {
+        0 => log::LevelFilter::Error,
+        1 => log::LevelFilter::Warn,
+        2 => log::LevelFilter::Info,
+        3 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+fn format_entry(time: String, record: &log::Record) -> String {
+    serde_json::to_string(&Entry {
+        time,
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+    })
+    .unwrap()
+}
+
+impl JsonLogger {
+    pub fn init(verbosity: usize, quiet: bool) -> Result<(), log::SetLoggerError> {
+        let level = level(verbosity, quiet);
+        log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let time = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+        // there is nowhere to report a failure to write a log message
+        let _ = writeln!(std::io::stderr(), "{}", format_entry(time, record));
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entry() {
+        let record = log::Record::builder()
+            .args(format_args!("Can't connect to \"redis\""))
+            .level(log::Level::Warn)
+            .target("rslog")
+            .build();
+        assert_eq!(
+            format_entry("2021-01-01T00:00:00+00:00".to_owned(), &record),
+            r#"{"time":"2021-01-01T00:00:00+00:00","level":"WARN","target":"rslog","message":"Can't connect to \"redis\""}"#
+        );
+        assert_eq!(level(0, true), log::LevelFilter::Off);
+        assert_eq!(level(2, false), log::LevelFilter::Info);
+    }
+}
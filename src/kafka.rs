@@ -0,0 +1,85 @@
+use crate::output::{Entry, Sink};
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaResult;
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::ClientContext;
+use rsloglib::SlowlogRecord;
+use std::io;
+use std::time::Duration;
+
+// Logs messages that couldn't be delivered, called from the polling thread of the producer
+struct DeliveryLogger;
+
+impl ClientContext for DeliveryLogger {}
+
+impl ProducerContext for DeliveryLogger {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult, _: ()) {
+        if let Err((e, _)) = result {
+            log::error!("Can't deliver record to Kafka: {}", e)
+        }
+    }
+}
+
+/// Produces records as JSON messages, librdkafka buffers them and sends them
+/// from its own threads, so reading the slowlog never waits for the brokers
+pub struct Kafka {
+    producer: ThreadedProducer<DeliveryLogger>,
+    topic: String,
+}
+
+impl Kafka {
+    pub fn connect(brokers: &str, topic: String) -> KafkaResult<Kafka> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create_with_context(DeliveryLogger)?;
+        Ok(Kafka { producer, topic })
+    }
+
+    pub fn send(&self, key: &str, record: &serde_json::Value) {
+        let payload = serde_json::to_vec(record).unwrap();
+        let message = BaseRecord::to(&self.topic).key(key).payload(&payload);
+        if let Err((e, _)) = self.producer.send(message) {
+            log::error!("Can't queue record for Kafka: {}", e)
+        }
+    }
+}
+
+// Messages of a command go to the same partition, and of the same server when there are many of them
+fn kafka_key(r: &SlowlogRecord, server: Option<&str>) -> String {
+    let command = r
+        .command
+        .first()
+        .map_or(String::new(), |c| c.to_uppercase());
+    match server {
+        Some(server) => format!("{}/{}", server, command),
+        None => command,
+    }
+}
+
+impl Sink for Kafka {
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if let Some(json) = entry.json {
+            self.send(&kafka_key(entry.record, entry.server), json)
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&mut self, _line: &str, json: &serde_json::Value) -> io::Result<()> {
+        self.send("heartbeat", json);
+        Ok(())
+    }
+
+    /// Waits up to 10 seconds for queued messages to be delivered
+    fn close(&mut self) -> io::Result<()> {
+        if let Err(e) = self.producer.flush(Duration::from_secs(10)) {
+            log::error!("Can't deliver queued records to Kafka: {}", e)
+        }
+        Ok(())
+    }
+
+    fn wants_json(&self) -> bool {
+        true
+    }
+}
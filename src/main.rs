@@ -1,115 +1,1818 @@
-use std::convert::TryFrom;
-use std::thread::sleep;
-use std::time::Duration;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::{Duration, Instant};
 
+mod aggregate;
 mod argument_parsing;
-use argument_parsing::OutputFormat;
+mod checkpoint;
+mod dedup;
+mod fields;
+mod json_log;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod metrics;
+mod output;
+mod rate_limit;
+mod stats;
+mod statsd;
+mod template;
+mod watch;
+use argument_parsing::{Config, OutputFormat};
+use dedup::{Dedup, Duplicates};
+use fields::Field;
+use metrics::Metrics;
+use output::{Broadcast, Entry, Output, RotatingFile, Stdout, Syslog, Webhook};
+use rate_limit::RateLimiter;
+use statsd::Statsd;
 
-use rsloglib::{RedisConnectionProvider, SlowlogReader, SlowlogRecord};
+use rsloglib::{
+    csv_field, human_duration, logfmt_value, truncate_command, tsv_field, Context, FollowFrom,
+    RedisConnectionProvider, RslogError, Sentinel, SlowlogReader, SlowlogReaderBuilder,
+    SlowlogRecord,
+};
 
-fn print_rec(r: &SlowlogRecord, format: &OutputFormat) {
-    match format {
+// Set on SIGINT or SIGTERM to stop following and exit
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// Set when metrics are exported with --metrics-addr
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+// Set when metrics are pushed with --statsd
+static STATSD: OnceLock<Statsd> = OnceLock::new();
+// Reconnections to all servers, for --status-interval
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+// Set to --total-timeout by its watchdog once the run took longer
+static TIMED_OUT: OnceLock<Duration> = OnceLock::new();
+// How long the watchdog waits for main to close the output and restore slowlog settings after
+// --total-timeout before it ends the process
+const TOTAL_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+// Exit codes, the mapping is part of the interface and documented in README
+const EXIT_FAILURE: i32 = 1;
+const EXIT_AUTH: i32 = 2;
+const EXIT_CONNECTION: i32 = 3;
+const EXIT_CONFIG: i32 = 4;
+
+const DIM: &str = "2";
+const BOLD: &str = "1";
+const RED: &str = "31";
+
+fn paint<T: std::fmt::Display>(value: T, style: &str, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", style, value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_time(time: u64, config: &Config) -> String {
+    fn render<Tz: chrono::TimeZone>(time: chrono::DateTime<Tz>, format: &str) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        if format == "rfc3339" {
+            time.to_rfc3339()
+        } else {
+            time.format(format).to_string()
+        }
+    }
+    match &config.time_format {
+        None => time.to_string(),
+        Some(format) => {
+            let utc = chrono::DateTime::from_timestamp(time as i64, 0).unwrap_or_default();
+            if config.utc {
+                render(utc, format)
+            } else {
+                render(utc.with_timezone(&chrono::Local), format)
+            }
+        }
+    }
+}
+
+// Version of the serialized record, to be increased whenever its fields change
+const SCHEMA_VERSION: u32 = 3;
+
+// Adds the schema version and the server a record came from to serialized output
+#[derive(Serialize)]
+struct TaggedRecord<'a, R: Serialize> {
+    schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    // number of identical commands with --dedup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+    #[serde(flatten)]
+    record: R,
+    // of the whole command, even if it was truncated
+    arg_count: usize,
+    payload_bytes: usize,
+}
+
+// Record without empty fields for --compact-json
+#[derive(Serialize)]
+struct CompactRecord<'a> {
+    id: u64,
+    time: u64,
+    duration: u64,
+    command: &'a [String],
+    #[serde(skip_serializing_if = "str::is_empty")]
+    client_socket: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    client_name: &'a str,
+}
+
+impl<'a> From<&'a SlowlogRecord> for CompactRecord<'a> {
+    fn from(r: &'a SlowlogRecord) -> CompactRecord<'a> {
+        CompactRecord {
+            id: r.id,
+            time: r.time,
+            duration: r.duration,
+            command: &r.command,
+            client_socket: &r.client_socket,
+            client_name: &r.client_name,
+        }
+    }
+}
+
+fn to_json<R: Serialize>(record: &TaggedRecord<R>, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(record).unwrap()
+    } else {
+        serde_json::to_string(record).unwrap()
+    }
+}
+
+// Written by --heartbeat when no records were printed for a while
+#[derive(Serialize)]
+struct Heartbeat {
+    schema_version: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    time: u64,
+}
+
+impl Heartbeat {
+    fn new(time: u64) -> Heartbeat {
+        Heartbeat {
+            schema_version: SCHEMA_VERSION,
+            kind: "heartbeat",
+            time,
+        }
+    }
+}
+
+// The heartbeat in the output format, sinks that stream JSON get the JSON object whatever the format is
+fn format_heartbeat(heartbeat: &Heartbeat, config: &Config) -> String {
+    match &config.output_format {
+        OutputFormat::Json if config.pretty => serde_json::to_string_pretty(heartbeat).unwrap(),
+        OutputFormat::Json => serde_json::to_string(heartbeat).unwrap(),
+        OutputFormat::Yaml => format!(
+            "---\n{}",
+            serde_yaml::to_string(heartbeat).unwrap().trim_end()
+        ),
+        OutputFormat::Logfmt => format!(
+            "type=heartbeat time={}",
+            logfmt_value(&format_time(heartbeat.time, config))
+        ),
+        // other formats are rejected with --heartbeat
+        _ => format!("[{}] heartbeat", format_time(heartbeat.time, config)),
+    }
+}
+
+fn format_rec(
+    r: &SlowlogRecord,
+    server: Option<&str>,
+    count: Option<u64>,
+    config: &Config,
+) -> String {
+    let time = format_time(r.time, config);
+    let (arg_count, payload_bytes) = (r.arg_count(), r.payload_bytes());
+    let (command, truncated) = truncate_command(&r.command, config.max_arg_len, config.max_args);
+    let shortened;
+    let r = if truncated {
+        shortened = SlowlogRecord {
+            command,
+            ..r.clone()
+        };
+        &shortened
+    } else {
+        r
+    };
+    if let Some(fields) = &config.fields {
+        return format_fields(r, &time, server, count, truncated, fields, config);
+    }
+    let tagged = TaggedRecord {
+        schema_version: SCHEMA_VERSION,
+        server,
+        truncated,
+        count,
+        record: r,
+        arg_count,
+        payload_bytes,
+    };
+    match &config.output_format {
+        // same as rsloglib::format_text, but with colors and --human-duration
+        OutputFormat::Text => format!(
+            "{}[{}] id: {},\tduration: {},\tclient: {},\tclient_name: {},\tcommand: {}{}",
+            server.map_or(String::new(), |s| format!("{} ", s)),
+            time,
+            paint(r.id, DIM, config.color),
+            format_duration(r, config),
+            r.client_socket,
+            r.client_name,
+            paint(format!("{:?}", r.command), BOLD, config.color),
+            count.map_or(String::new(), |c| format!(",\tcount: {}", c))
+        ),
+        OutputFormat::Json if config.compact_json => to_json(
+            &TaggedRecord {
+                schema_version: SCHEMA_VERSION,
+                server,
+                truncated,
+                count,
+                record: CompactRecord::from(r),
+                arg_count,
+                payload_bytes,
+            },
+            config.pretty,
+        ),
+        OutputFormat::Json => to_json(&tagged, config.pretty),
+        OutputFormat::Csv => format!(
+            "{}{}{}",
+            server.map_or(String::new(), |s| format!("{},", csv_field(s))),
+            rsloglib::format_csv(r, &time),
+            count.map_or(String::new(), |c| format!(",{}", c))
+        ),
+        OutputFormat::Tsv => format!(
+            "{}{}{}",
+            server.map_or(String::new(), |s| format!("{}\t", tsv_field(s))),
+            rsloglib::format_tsv(r, &time),
+            count.map_or(String::new(), |c| format!("\t{}", c))
+        ),
+        OutputFormat::Yaml => {
+            // every record is a separate document so the stream can be read while following
+            format!(
+                "---\n{}",
+                serde_yaml::to_string(&tagged).unwrap().trim_end()
+            )
+        }
+        OutputFormat::Logfmt => format!(
+            "{}{}{}",
+            server.map_or(String::new(), |s| format!("server={} ", logfmt_value(s))),
+            rsloglib::format_logfmt(r, &time),
+            count.map_or(String::new(), |c| format!(" count={}", c))
+        ),
+        OutputFormat::Template(t) => {
+            t.render(r, &time, server.unwrap_or_default(), count.unwrap_or(1))
+        }
+    }
+}
+
+fn format_duration(r: &SlowlogRecord, config: &Config) -> String {
+    paint(
+        if config.human_duration {
+            human_duration(r.duration)
+        } else {
+            r.duration.to_string()
+        },
+        RED,
+        config.color && r.duration > config.color_threshold,
+    )
+}
+
+// Formats only the --fields in their order, other tags are added as without --fields
+fn format_fields(
+    r: &SlowlogRecord,
+    time: &str,
+    server: Option<&str>,
+    count: Option<u64>,
+    truncated: bool,
+    fields: &[Field],
+    config: &Config,
+) -> String {
+    // the value of a field in formats without types
+    let plain = |field: &Field| match field {
+        Field::Time => time.to_owned(),
+        Field::Id => r.id.to_string(),
+        Field::Duration => r.duration.to_string(),
+        Field::ClientSocket => r.client_socket.clone(),
+        Field::ClientName => r.client_name.clone(),
+        Field::Command => r.command.join(" "),
+    };
+    match &config.output_format {
         OutputFormat::Text => {
-            println!(
-                "[{}] id: {},\tduration: {},\tclient: {},\tclient_name: {},\tcommand: {:?}",
-                r.time, r.id, r.duration, r.client_socket, r.client_name, r.command
+            let values: Vec<_> = fields
+                .iter()
+                .filter_map(|field| match field {
+                    Field::Time => None,
+                    Field::Id => Some(format!("id: {}", paint(r.id, DIM, config.color))),
+                    Field::Duration => Some(format!("duration: {}", format_duration(r, config))),
+                    Field::ClientSocket => Some(format!("client: {}", r.client_socket)),
+                    Field::ClientName => Some(format!("client_name: {}", r.client_name)),
+                    Field::Command => Some(format!(
+                        "command: {}",
+                        paint(format!("{:?}", r.command), BOLD, config.color)
+                    )),
+                })
+                .chain(count.map(|c| format!("count: {}", c)))
+                .collect();
+            format!(
+                "{}{}{}",
+                server.map_or(String::new(), |s| format!("{} ", s)),
+                if fields.contains(&Field::Time) {
+                    format!("[{}] ", time)
+                } else {
+                    String::new()
+                },
+                values.join(",\t")
             )
         }
-        OutputFormat::Json => {
-            println!("{}", serde_json::to_string(r).unwrap())
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let mut object = serde_json::Map::new();
+            object.insert("schema_version".to_owned(), SCHEMA_VERSION.into());
+            if let Some(server) = server {
+                object.insert("server".to_owned(), server.into());
+            }
+            if truncated {
+                object.insert("truncated".to_owned(), true.into());
+            }
+            if let Some(count) = count {
+                object.insert("count".to_owned(), count.into());
+            }
+            for field in fields {
+                let value: serde_json::Value = match field {
+                    Field::Time => r.time.into(),
+                    Field::Id => r.id.into(),
+                    Field::Duration => r.duration.into(),
+                    Field::ClientSocket => r.client_socket.clone().into(),
+                    Field::ClientName => r.client_name.clone().into(),
+                    Field::Command => r.command.clone().into(),
+                };
+                let empty = value.as_str() == Some("");
+                if !(config.compact_json && empty) {
+                    object.insert(field.name().to_owned(), value);
+                }
+            }
+            match config.output_format {
+                OutputFormat::Json if config.pretty => {
+                    serde_json::to_string_pretty(&object).unwrap()
+                }
+                OutputFormat::Json => serde_json::to_string(&object).unwrap(),
+                _ => format!(
+                    "---\n{}",
+                    serde_yaml::to_string(&object).unwrap().trim_end()
+                ),
+            }
+        }
+        OutputFormat::Csv => server
+            .map(csv_field)
+            .into_iter()
+            .chain(fields.iter().map(|f| csv_field(&plain(f))))
+            .chain(count.map(|c| c.to_string()))
+            .collect::<Vec<_>>()
+            .join(","),
+        OutputFormat::Tsv => server
+            .map(tsv_field)
+            .into_iter()
+            .chain(fields.iter().map(|f| tsv_field(&plain(f))))
+            .chain(count.map(|c| c.to_string()))
+            .collect::<Vec<_>>()
+            .join("\t"),
+        OutputFormat::Logfmt => server
+            .map(|s| format!("server={}", logfmt_value(s)))
+            .into_iter()
+            .chain(fields.iter().map(|f| {
+                // the socket is called client in logfmt like in text
+                let name = match f {
+                    Field::ClientSocket => "client",
+                    f => f.name(),
+                };
+                format!("{}={}", name, logfmt_value(&plain(f)))
+            }))
+            .chain(count.map(|c| format!("count={}", c)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        // --fields conflicts with --template
+        OutputFormat::Template(_) => unreachable!(),
+    }
+}
+
+fn print_rec(
+    out: &mut Output,
+    r: &SlowlogRecord,
+    server: Option<&str>,
+    count: Option<u64>,
+    config: &Config,
+) -> std::io::Result<()> {
+    let json = if out.streams_json() {
+        Some(
+            serde_json::to_value(TaggedRecord {
+                schema_version: SCHEMA_VERSION,
+                server,
+                truncated: false,
+                count,
+                record: r,
+                arg_count: r.arg_count(),
+                payload_bytes: r.payload_bytes(),
+            })
+            .unwrap(),
+        )
+    } else {
+        None
+    };
+    out.write_record(&Entry {
+        record: r,
+        server,
+        line: &format_rec(r, server, count, config),
+        json: json.as_ref(),
+    })?;
+    if config.flush {
+        out.flush()?
+    }
+    Ok(())
+}
+
+fn print_header(out: &mut Output, config: &Config, with_server: bool) -> std::io::Result<()> {
+    let separator = match config.output_format {
+        OutputFormat::Csv => ",",
+        OutputFormat::Tsv => "\t",
+        _ => return Ok(()),
+    };
+    let columns: Vec<_> = config
+        .fields
+        .as_deref()
+        .unwrap_or(&fields::ALL)
+        .iter()
+        .map(|f| f.name())
+        .collect();
+    let server = if with_server {
+        format!("server{}", separator)
+    } else {
+        String::new()
+    };
+    let count = if config.dedup.is_some() {
+        format!("{}count", separator)
+    } else {
+        String::new()
+    };
+    out.write(&format!("{}{}{}\n", server, columns.join(separator), count))
+}
+
+fn error_handler(e: &RslogError) {
+    match e {
+        RslogError::Connection { source, .. } if source.is_timeout() => {
+            log::error!("Timed out waiting for redis server. {}", e)
+        }
+        RslogError::Connection { .. } | RslogError::Auth { .. } => log::error!("{}", e),
+        RslogError::Redis { source, .. } => log::error!("{} ({:?})", e, source.kind()),
+    }
+}
+
+// Logs errors that are worth retrying and passes on the ones that aren't,
+// authentication failures are only retried with --retry-auth-errors
+fn handle_error(e: RslogError, config: &Config) -> Result<(), RslogError> {
+    match e {
+        RslogError::Auth { .. } if !config.retry_auth_errors => Err(e),
+        e => {
+            error_handler(&e);
+            Ok(())
+        }
+    }
+}
+
+fn exit_code(e: &RslogError) -> i32 {
+    match e {
+        RslogError::Auth { .. } => EXIT_AUTH,
+        RslogError::Connection { .. } => EXIT_CONNECTION,
+        RslogError::Redis { .. } => EXIT_FAILURE,
+    }
+}
+
+// The only place where an error from redis ends the process
+fn exit_on_error(e: RslogError) -> ! {
+    error_handler(&e);
+    std::process::exit(exit_code(&e))
+}
+
+// Why reading stopped before it was done, the server failed, the output can't be written
+// or --total-timeout was exceeded
+enum Failure {
+    Redis(RslogError),
+    Output(std::io::Error),
+    TimedOut(Duration),
+}
+
+impl From<RslogError> for Failure {
+    fn from(e: RslogError) -> Failure {
+        Failure::Redis(e)
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Failure {
+        Failure::Output(e)
+    }
+}
+
+fn exit_on_failure(failure: Failure) -> ! {
+    match failure {
+        Failure::Redis(e) => exit_on_error(e),
+        Failure::TimedOut(timeout) => {
+            log::error!("Gave up after the total timeout of {:?}", timeout);
+            std::process::exit(EXIT_CONNECTION)
+        }
+        // the reader went away, like head in `rslog | head`, which isn't a failure of rslog
+        Failure::Output(e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Failure::Output(e) => {
+            log::error!("Can't write output: {}", e);
+            std::process::exit(EXIT_FAILURE)
         }
     }
 }
 
-fn error_handler(e: redis::RedisError) {
-    match e.kind() {
-        redis::ErrorKind::IoError => {
-            log::error!("Can't establish connection to redis cluster: {}", e)
+fn check_total_timeout() -> Result<(), Failure> {
+    TIMED_OUT
+        .get()
+        .map_or(Ok(()), |timeout| Err(Failure::TimedOut(*timeout)))
+}
+
+// Runs `read` for every server even if some of them fail. Rejected credentials, output errors and
+// --total-timeout stop right away, otherwise the last error is returned after all servers were read
+// and earlier ones are only logged.
+fn for_each_server(
+    servers: Vec<(Option<String>, RedisConnectionProvider)>,
+    mut read: impl FnMut(Option<String>, RedisConnectionProvider) -> Result<(), Failure>,
+) -> Result<(), Failure> {
+    let mut failure = None;
+    for (server, con_provider) in servers {
+        match read(server, con_provider) {
+            Ok(()) => (),
+            Err(Failure::Redis(e @ RslogError::Auth { .. })) => return Err(e.into()),
+            Err(Failure::Redis(e)) => {
+                if let Some(previous) = failure.replace(e) {
+                    error_handler(&previous)
+                }
+            }
+            Err(e) => return Err(e),
         }
-        redis::ErrorKind::AuthenticationFailed => {
-            log::error!("{:?}: {}", e.kind(), e);
-            std::process::exit(1);
+        // a read that failed because of the timeout is logged, but the timeout is returned
+        if let Err(e) = check_total_timeout() {
+            if let Some(previous) = failure {
+                error_handler(&previous)
+            }
+            return Err(e);
         }
-        redis::ErrorKind::ExtensionError => {
-            log::error!("{:?}: {}", e.kind(), e);
-            std::process::exit(1);
+    }
+    failure.map_or(Ok(()), |e| Err(e.into()))
+}
+
+fn stopped(stop: &AtomicBool) -> bool {
+    stop.load(Ordering::Relaxed) || SHUTDOWN.load(Ordering::Relaxed)
+}
+
+// Sleeps in short steps so shutdown doesn't have to wait for the whole interval
+fn sleep(duration: Duration, stop: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        if stopped(stop) {
+            return;
         }
-        _ => unimplemented!("Error not handled: {}({:?})", e, e.kind()),
+        std::thread::sleep(left.min(Duration::from_millis(100)))
     }
 }
 
-fn create_slowlog_reader(con_provider: RedisConnectionProvider, interval: u64) -> SlowlogReader {
+// Doubles the delay on every failed attempt up to the maximum and adds up to 10% of random jitter
+// so many instances don't reconnect at the same moment
+fn backoff(attempt: u32, config: &Config) -> Duration {
+    let delay = Duration::from_secs(config.backoff_base)
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(Duration::from_secs(config.max_backoff));
+    delay + delay.mul_f64(rand::random::<f64>() / 10.0)
+}
+
+fn reconnected() {
+    RECONNECTS.fetch_add(1, Ordering::Relaxed);
+    if let Some(metrics) = METRICS.get() {
+        metrics.reconnected()
+    }
+}
+
+// Whether --max-reconnects consecutive connection failures happened
+fn out_of_reconnects(failures: u32, config: &Config) -> bool {
+    config.max_reconnects > 0 && failures >= config.max_reconnects
+}
+
+fn create_slowlog_reader(
+    con_provider: RedisConnectionProvider,
+    config: &Config,
+    stop: &AtomicBool,
+) -> Result<Option<SlowlogReader>, RslogError> {
     log::debug!("Creating slowlog reader");
-    loop {
-        match SlowlogReader::try_from(con_provider.clone()) {
-            Err(e) => error_handler(e),
-            Ok(slr) => return slr,
+    let mut attempt = 0;
+    while !stopped(stop) {
+        match SlowlogReaderBuilder::new(con_provider.clone())
+            .length(config.count)
+            .last_id(config.since_id.map_or(-1, |id| id as i64))
+            // a resumed reader must not skip the records logged while rslog was stopped
+            .follow_from(match config.since_id {
+                Some(_) => FollowFrom::Beginning,
+                None => config.follow_from,
+            })
+            .filter(config.filter.clone())
+            .interval(config.interval)
+            .restart_check_interval(config.restart_check_interval)
+            .slowlog_command(config.slowlog_command.clone())
+            .reconnect_kinds(config.reconnect_kinds.clone())
+            .build()
+        {
+            Err(e @ RslogError::Connection { .. }) if out_of_reconnects(attempt + 1, config) => {
+                log::error!("Giving up after {} failed connection attempts", attempt + 1);
+                return Err(e);
+            }
+            Err(e) => handle_error(e, config)?,
+            Ok(slr) => return Ok(Some(slr)),
+        }
+        let delay = backoff(attempt, config);
+        log::info!("Retrying connection in {:.1}s", delay.as_secs_f64());
+        sleep(delay, stop);
+        attempt += 1
+    }
+    Ok(None)
+}
+
+fn redact(mut r: SlowlogRecord, config: &Config) -> SlowlogRecord {
+    if let Some(redaction) = &config.redaction {
+        redaction.apply(&mut r)
+    }
+    r
+}
+
+// Explains why slowlog may stay empty. CONFIG is often disabled on managed servers,
+// so not being able to read it is only worth a debug message.
+fn report_slowlog_config(
+    slowlog_config: Result<rsloglib::SlowlogConfig, RslogError>,
+    server: Option<&str>,
+) {
+    let server = server.map_or(String::new(), |s| format!("{}: ", s));
+    let slowlog_config = match slowlog_config {
+        Ok(slowlog_config) => slowlog_config,
+        Err(e) => return log::debug!("{}{}", server, e),
+    };
+    log::info!(
+        "{}slowlog-log-slower-than is {}us, slowlog-max-len is {}",
+        server,
+        slowlog_config.log_slower_than,
+        slowlog_config.max_len
+    );
+    match slowlog_config.log_slower_than {
+        -1 => log::warn!(
+            "{}Slowlog is disabled on the server, slowlog-log-slower-than is -1, no records will appear",
+            server
+        ),
+        0 => log::warn!(
+            "{}Every command is logged, slowlog-log-slower-than is 0, which slows the server down",
+            server
+        ),
+        _ => (),
+    }
+}
+
+// Returns filtered and redacted records
+fn get_records(
+    con_provider: RedisConnectionProvider,
+    server: Option<&str>,
+    since_id: Option<u64>,
+    config: &Config,
+) -> Result<Vec<SlowlogRecord>, RslogError> {
+    Ok(con_provider
+        .get_connection()
+        .and_then(|mut con| {
+            let slowlog_config = rsloglib::slowlog_config(&mut con);
+            let count = match (config.tail, &slowlog_config) {
+                (Some(tail), Ok(slowlog_config)) => {
+                    (tail as u64).min(slowlog_config.max_len) as u32
+                }
+                (Some(tail), Err(_)) => tail,
+                (None, _) => config.count,
+            };
+            report_slowlog_config(slowlog_config.context("Can't read slowlog config"), server);
+            rsloglib::get_slowlog_with(&mut con, &config.slowlog_command, count)
+        })
+        .context("Can't read slowlog")?
+        .into_iter()
+        .filter(|r| since_id.is_none_or(|id| r.id > id))
+        .filter(|r| config.filter.matches(r))
+        .map(|r| redact(r, config))
+        .collect())
+}
+
+// Ids of the newest record of every server when --new-only started
+type Marks = HashMap<Option<String>, u64>;
+
+fn since_id(marks: &Marks, server: Option<&str>, config: &Config) -> Option<u64> {
+    marks
+        .get(&server.map(|s| s.to_owned()))
+        .copied()
+        .max(config.since_id)
+}
+
+fn mark_servers(
+    servers: &[(Option<String>, RedisConnectionProvider)],
+    config: &Config,
+) -> Result<Marks, RslogError> {
+    let mut marks = Marks::new();
+    for (server, con_provider) in servers {
+        let newest = con_provider
+            .get_connection()
+            .and_then(|mut con| rsloglib::get_slowlog_with(&mut con, &config.slowlog_command, 1))
+            .context("Can't read slowlog")?;
+        // everything is new on a server with an empty slowlog
+        if let Some(r) = newest.first() {
+            marks.insert(server.clone(), r.id);
         }
-        sleep(Duration::new(interval, 0))
     }
+    Ok(marks)
 }
 
-fn read_once(con_provider: RedisConnectionProvider, config: &argument_parsing::Config) {
-    match {
-        move || -> Result<(), redis::RedisError> {
-            for r in rsloglib::get_slowlog(&mut con_provider.get_connection()?, 128)?.iter() {
-                print_rec(r, &config.output_format)
+// Waits for --for or until Ctrl-C without it
+fn wait_for_new_records(config: &Config) {
+    match config.capture_for {
+        Some(duration) => {
+            log::info!("Capturing new records for {:?}", duration);
+            sleep(duration, &AtomicBool::new(false))
+        }
+        None => {
+            log::info!("Capturing new records until interrupted");
+            while !SHUTDOWN.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(100))
+            }
+        }
+    }
+}
+
+fn read_once(
+    out: &mut Output,
+    con_provider: RedisConnectionProvider,
+    server: Option<&str>,
+    since_id: Option<u64>,
+    config: &Config,
+) -> Result<usize, Failure> {
+    let mut records = get_records(con_provider, server, since_id, config)?;
+    let found = records.len();
+    // slowlog returns newest records first
+    if !config.newest_first {
+        records.reverse()
+    }
+    match config.dedup {
+        Some(window) => {
+            // the window goes forward in time, so records are collapsed oldest first
+            if config.newest_first {
+                records.reverse()
+            }
+            let mut dedup = Dedup::new(window);
+            let mut groups = dedup.add(server, records);
+            groups.extend(dedup.finish());
+            if config.newest_first {
+                groups.reverse()
             }
+            for g in groups {
+                print_rec(out, &g.record, server, Some(g.count), config)?
+            }
+        }
+        None => {
+            for r in records {
+                print_rec(out, &r, server, None, config)?
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Returns records from servers that could be read along with the error from the others
+fn collect_records(
+    servers: Vec<(Option<String>, RedisConnectionProvider)>,
+    marks: &Marks,
+    config: &Config,
+) -> (Vec<SlowlogRecord>, Result<(), Failure>) {
+    let mut records = Vec::new();
+    let result = for_each_server(servers, |server, con_provider| {
+        let since_id = since_id(marks, server.as_deref(), config);
+        records.extend(get_records(
+            con_provider,
+            server.as_deref(),
+            since_id,
+            config,
+        )?);
+        Ok(())
+    });
+    (records, result)
+}
+
+// Returns the number of records that were read
+fn read_all_once(
+    out: &mut Output,
+    servers: Vec<(Option<String>, RedisConnectionProvider)>,
+    config: &Config,
+) -> Result<usize, Failure> {
+    let servers = if config.cluster {
+        cluster_servers(servers.into_iter().next().unwrap().1, config)?
+    } else {
+        servers
+    };
+    let marks = if config.new_only {
+        let marks = mark_servers(&servers, config)?;
+        wait_for_new_records(config);
+        marks
+    } else {
+        Marks::new()
+    };
+    if config.aggregate || config.count_by_client.is_some() {
+        let (records, result) = collect_records(servers, &marks, config);
+        aggregate_once(out, &records, config)?;
+        result.map(|()| records.len())
+    } else if config.stats {
+        let (records, result) = collect_records(servers, &marks, config);
+        let durations: Vec<_> = records.iter().map(|r| r.duration).collect();
+        let found = durations.len();
+        print_stats(out, &stats::duration_stats(durations), config)?;
+        result.map(|()| found)
+    } else {
+        let mut found = 0;
+        for_each_server(servers, |server, con_provider| {
+            let since_id = since_id(&marks, server.as_deref(), config);
+            found += read_once(out, con_provider, server.as_deref(), since_id, config)?;
+            // records of servers that were read aren't lost if a later one blocks past --total-timeout
+            out.flush()?;
+            Ok(())
+        })
+        .map(|()| found)
+    }
+}
+
+fn aggregate_once(
+    out: &mut Output,
+    records: &[SlowlogRecord],
+    config: &Config,
+) -> std::io::Result<()> {
+    let (mut stats, column) = match config.count_by_client {
+        Some(by) => (aggregate::count_by_client(records, by), by.column()),
+        None => (aggregate::aggregate(records), "command"),
+    };
+    if config.top > 0 {
+        stats.truncate(config.top)
+    }
+    out.write(&aggregate::render_table(&stats, column, config.header))
+}
+
+fn print_stats(
+    out: &mut Output,
+    stats: &stats::DurationStats,
+    config: &Config,
+) -> std::io::Result<()> {
+    let report = match config.output_format {
+        OutputFormat::Json => serde_json::to_string(stats).unwrap(),
+        _ => stats.render_text(),
+    };
+    out.write_line(&report)?;
+    out.flush()
+}
+
+// Connects and runs the commands rslog needs, returns whether everything worked
+fn check(con_provider: RedisConnectionProvider, server: Option<&str>) -> Result<(), RslogError> {
+    let result = con_provider
+        .get_connection()
+        .and_then(|mut con| {
+            redis::cmd("PING").query::<String>(&mut con)?;
+            Ok((
+                rsloglib::slowlog_len(&mut con)?,
+                rsloglib::server_info(&mut con)?,
+            ))
+        })
+        .context("Check failed");
+    let server = server.map_or(String::new(), |s| format!("{} ", s));
+    match result {
+        Ok((len, info)) => {
+            println!(
+                "{}OK: redis {}, uptime {}s, {} records in slowlog",
+                server,
+                if info.version.is_empty() {
+                    "of unknown version"
+                } else {
+                    &info.version
+                },
+                info.uptime,
+                len
+            );
             Ok(())
         }
-    }() {
-        Err(e) => error_handler(e),
-        Ok(_) => std::process::exit(0),
+        Err(e) => {
+            let source = e.redis_error();
+            println!("{}FAILED: {:?}: {}", server, source.kind(), source);
+            Err(e)
+        }
     }
 }
 
-fn read_continiously(con_provider: RedisConnectionProvider, config: &argument_parsing::Config) {
-    let mut sl_reader = create_slowlog_reader(con_provider, config.interval);
+// Prints version, mode and uptime of the server for --server-info
+fn print_server_info(
+    con_provider: RedisConnectionProvider,
+    server: Option<&str>,
+) -> Result<(), RslogError> {
+    let info = con_provider
+        .get_connection()
+        .and_then(|mut con| rsloglib::server_info(&mut con))
+        .context("Can't read server info")?;
+    let unknown = |value: String| {
+        if value.is_empty() {
+            "unknown".to_owned()
+        } else {
+            value
+        }
+    };
+    println!(
+        "{}redis {}, {} mode, uptime {}s",
+        server.map_or(String::new(), |s| format!("{} ", s)),
+        unknown(info.version),
+        unknown(info.mode),
+        info.uptime
+    );
+    Ok(())
+}
+
+type PreviousConfig = (
+    Option<String>,
+    RedisConnectionProvider,
+    rsloglib::SlowlogConfig,
+);
 
-    loop {
-        match sl_reader
-            .get()
-            .map_err(|e| sl_reader.redis_error_handler(e))
-        {
+// Applies --set-threshold and --set-max-len to every server and returns the settings they had.
+// If a server fails the ones changed before it are put back, so either all or none are changed.
+fn configure_slowlog(
+    servers: &[(Option<String>, RedisConnectionProvider)],
+    config: &Config,
+) -> Result<Vec<PreviousConfig>, RslogError> {
+    let mut previous = Vec::new();
+    for (server, con_provider) in servers {
+        let result = con_provider
+            .get_connection()
+            .and_then(|mut con| {
+                let slowlog_config = rsloglib::slowlog_config(&mut con)?;
+                rsloglib::set_slowlog_config(&mut con, config.set_threshold, config.set_max_len)?;
+                Ok(slowlog_config)
+            })
+            .context("Can't change slowlog config");
+        let slowlog_config = match result {
+            Ok(slowlog_config) => slowlog_config,
+            Err(e) => {
+                restore_slowlog(previous, config);
+                return Err(e);
+            }
+        };
+        log::info!(
+            "{}Changed slowlog config",
+            server
+                .as_ref()
+                .map_or(String::new(), |s| format!("{}: ", s))
+        );
+        previous.push((server.clone(), con_provider.clone(), slowlog_config))
+    }
+    Ok(previous)
+}
+
+// Puts back only the settings that were changed, failures are logged so other servers are still restored
+fn restore_slowlog(previous: Vec<PreviousConfig>, config: &Config) {
+    for (server, con_provider, slowlog_config) in previous {
+        let server = server.map_or(String::new(), |s| format!("{}: ", s));
+        let result = con_provider
+            .get_connection()
+            .and_then(|mut con| {
+                rsloglib::set_slowlog_config(
+                    &mut con,
+                    config.set_threshold.map(|_| slowlog_config.log_slower_than),
+                    config.set_max_len.map(|_| slowlog_config.max_len),
+                )
+            })
+            .context("Can't restore slowlog config");
+        match result {
+            Ok(()) => log::info!("{}Restored slowlog config", server),
+            Err(e) => log::error!("{}{}", server, e),
+        }
+    }
+}
+
+fn print_len(con_provider: RedisConnectionProvider) -> Result<(), RslogError> {
+    let len = con_provider
+        .get_connection()
+        .and_then(|mut con| rsloglib::slowlog_len(&mut con))
+        .context("Can't get slowlog length")?;
+    println!("{}", len);
+    Ok(())
+}
+
+fn reset(con_provider: RedisConnectionProvider) -> Result<(), RslogError> {
+    let len = con_provider
+        .get_connection()
+        .and_then(|mut con| rsloglib::reset_slowlog(&mut con))
+        .context("Can't reset slowlog")?;
+    println!("Cleared {} slowlog records", len);
+    Ok(())
+}
+
+// Polls slowlog until `stop` is set or shutdown is requested and passes new records to `handle`, newest first,
+// along with the id of the newest record read so far. Returns early only on errors that retrying won't fix.
+fn follow(
+    con_provider: RedisConnectionProvider,
+    server: Option<&str>,
+    config: &Config,
+    stop: &AtomicBool,
+    mut handle: impl FnMut(Vec<SlowlogRecord>, i64),
+) -> Result<(), RslogError> {
+    if config.start_jitter {
+        let delay = config.interval.mul_f64(rand::random::<f64>());
+        log::debug!("Waiting {:?} before the first poll", delay);
+        sleep(delay, stop)
+    }
+    let mut sl_reader = match create_slowlog_reader(con_provider, config, stop)? {
+        Some(sl_reader) => sl_reader,
+        None => return Ok(()),
+    };
+    report_slowlog_config(sl_reader.slowlog_config(), server);
+
+    let mut failures = 0;
+    while !stopped(stop) {
+        let delay = match sl_reader.get() {
             Ok(records) => {
-                for r in records.iter().rev() {
-                    print_rec(r, &config.output_format)
+                failures = 0;
+                let records: Vec<_> = records.into_iter().map(|r| redact(r, config)).collect();
+                if let Some(metrics) = METRICS.get() {
+                    metrics.observe(&records)
+                }
+                if let Some(statsd) = STATSD.get() {
+                    statsd.send(&records)
+                }
+                handle(records, sl_reader.last_id());
+                config.interval
+            }
+            Err(e) if sl_reader.reconnects_on(&e) => {
+                failures += 1;
+                if out_of_reconnects(failures, config) {
+                    log::error!("Giving up after {} failed connection attempts", failures);
+                    return Err(e);
                 }
+                reconnected();
+                if let Err(e) = sl_reader.redis_error_handler(e) {
+                    handle_error(e, config)?
+                }
+                // a recovering server isn't polled on every interval
+                let delay = backoff(failures - 1, config);
+                log::info!("Polling again in {:.1}s", delay.as_secs_f64());
+                delay
             }
             Err(e) => {
-                if let Err(e) = e {
-                    error_handler(e)
+                handle_error(e, config)?;
+                config.interval
+            }
+        };
+        sleep(delay, stop);
+    }
+    if let Err(e) = sl_reader.close() {
+        log::debug!("{}", e)
+    }
+    Ok(())
+}
+
+// What is kept between polls while following
+struct FollowState {
+    printed: u64,
+    window: Option<stats::Window>,
+    checkpoint: i64,
+    rate: Option<RateLimiter>,
+    dedup: Option<Dedup>,
+    last_poll: Option<Instant>,
+    last_status: Instant,
+    last_output: Instant,
+}
+
+impl FollowState {
+    fn new(config: &Config) -> FollowState {
+        FollowState {
+            printed: 0,
+            window: config
+                .stats
+                .then(|| stats::Window::new(Duration::from_secs(config.stats_window))),
+            checkpoint: config.since_id.map_or(-1, |id| id as i64),
+            rate: config.max_rate.map(RateLimiter::new),
+            dedup: config.dedup.map(Dedup::new),
+            last_poll: None,
+            last_status: Instant::now(),
+            last_output: Instant::now(),
+        }
+    }
+
+    fn log_status(&self, prefix: &str) {
+        log::info!(
+            "{}: {} records printed, {} reconnects, last successful poll {}",
+            prefix,
+            self.printed,
+            RECONNECTS.load(Ordering::Relaxed),
+            self.last_poll.map_or("never".to_owned(), |t| format!(
+                "{}s ago",
+                t.elapsed().as_secs()
+            ))
+        )
+    }
+
+    // Logs the status line every --status-interval
+    fn report_status(&mut self, config: &Config) {
+        if let Some(interval) = config.status_interval {
+            if self.last_status.elapsed() >= interval {
+                self.log_status("Status");
+                self.last_status = Instant::now()
+            }
+        }
+    }
+
+    // Writes a heartbeat when nothing was printed for --heartbeat
+    fn heartbeat(&mut self, out: &mut Output, config: &Config) -> std::io::Result<()> {
+        if let Some(interval) = config.heartbeat {
+            if self.last_output.elapsed() >= interval {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let heartbeat = Heartbeat::new(now.as_secs());
+                let json = serde_json::to_value(&heartbeat).unwrap();
+                out.heartbeat(&format_heartbeat(&heartbeat, config), &json)?;
+                out.flush()?;
+                self.last_output = Instant::now()
+            }
+        }
+        Ok(())
+    }
+
+    fn report_final_status(&self, config: &Config) {
+        if config.status_interval.is_some() {
+            self.log_status("Stopped")
+        }
+    }
+
+    fn printed_enough(&self, config: &Config) -> bool {
+        config.max_records > 0 && self.printed >= config.max_records
+    }
+
+    // Saves the id of the newest record read once its records were printed, if it changed.
+    // After --max-records some records of the batch may be left unprinted, so the old id is kept.
+    fn save_checkpoint(&mut self, last_id: i64, config: &Config) {
+        let path = match &config.checkpoint_file {
+            Some(path) if last_id >= 0 && last_id != self.checkpoint => path,
+            _ => return,
+        };
+        if self.printed_enough(config) {
+            return;
+        }
+        match checkpoint::save(path, last_id as u64) {
+            Ok(()) => self.checkpoint = last_id,
+            Err(e) => log::error!("Can't write checkpoint to {}: {}", path.display(), e),
+        }
+    }
+}
+
+fn print_new_records(
+    out: &mut Output,
+    mut records: Vec<SlowlogRecord>,
+    server: Option<&str>,
+    config: &Config,
+    state: &mut FollowState,
+) -> std::io::Result<()> {
+    // it's called after every successful poll, even without new records
+    state.last_poll = Some(Instant::now());
+    if let Some(window) = &mut state.window {
+        if window.update(&records) {
+            print_stats(out, &window.stats(), config)?
+        }
+        return Ok(());
+    }
+    // slowlog returns newest records first
+    records.reverse();
+    let mut batch = match &mut state.dedup {
+        Some(dedup) => {
+            let mut groups = dedup.add(server, records);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            groups.extend(dedup.expire(now.as_secs()));
+            groups
+        }
+        None => records
+            .into_iter()
+            .map(|record| Duplicates {
+                server: server.map(|s| s.to_owned()),
+                record,
+                count: 1,
+            })
+            .collect(),
+    };
+    if config.newest_first {
+        batch.reverse()
+    }
+    print_batch(out, batch, config, state)
+}
+
+// Prints records that --dedup still holds back and the final status line when following stops
+fn finish_following(
+    out: &mut Output,
+    config: &Config,
+    state: &mut FollowState,
+) -> std::io::Result<()> {
+    if let Some(dedup) = &mut state.dedup {
+        let mut groups = dedup.finish();
+        if config.newest_first {
+            groups.reverse()
+        }
+        print_batch(out, groups, config, state)?
+    }
+    state.report_final_status(config);
+    Ok(())
+}
+
+fn print_batch(
+    out: &mut Output,
+    batch: Vec<Duplicates>,
+    config: &Config,
+    state: &mut FollowState,
+) -> std::io::Result<()> {
+    let mut dropped = 0;
+    for g in &batch {
+        // records that were already queued when --max-records was reached are dropped
+        if state.printed_enough(config) {
+            break;
+        }
+        if let Some(limiter) = &mut state.rate {
+            if !throttle(limiter, config) {
+                dropped += 1;
+                continue;
+            }
+        }
+        let count = config.dedup.map(|_| g.count);
+        print_rec(out, &g.record, g.server.as_deref(), count, config)?;
+        state.printed += 1;
+        if state.printed_enough(config) {
+            SHUTDOWN.store(true, Ordering::Relaxed)
+        }
+    }
+    // written in one go instead of a write for every record
+    if !batch.is_empty() {
+        state.last_output = Instant::now();
+        out.flush()?
+    }
+    if dropped > 0 {
+        log::warn!(
+            "Dropped {} records over the rate of {} records per second",
+            dropped,
+            config.max_rate.unwrap()
+        )
+    }
+    Ok(())
+}
+
+// Waits until --max-rate allows another record, or tells to drop it
+fn throttle(limiter: &mut RateLimiter, config: &Config) -> bool {
+    loop {
+        match limiter.take() {
+            Ok(()) => return true,
+            Err(_) if !config.rate_limit_buffer => return false,
+            // buffered records are printed without waiting on shutdown
+            Err(_) if SHUTDOWN.load(Ordering::Relaxed) => return true,
+            Err(wait) => std::thread::sleep(wait.min(Duration::from_millis(100))),
+        }
+    }
+}
+
+fn watch_row(r: &SlowlogRecord, server: Option<&str>, config: &Config) -> Vec<String> {
+    let (command, _) = truncate_command(&r.command, config.max_arg_len, config.max_args);
+    let mut row: Vec<_> = server.map(|s| s.to_owned()).into_iter().collect();
+    row.extend([
+        format_time(r.time, config),
+        r.id.to_string(),
+        if config.human_duration {
+            human_duration(r.duration)
+        } else {
+            r.duration.to_string()
+        },
+        r.client_socket.clone(),
+        r.client_name.clone(),
+        command.join(" "),
+    ]);
+    row
+}
+
+// Reprints the whole slowlog of all servers as a table every interval, like top
+fn watch(
+    out: &mut Output,
+    servers: Vec<(Option<String>, RedisConnectionProvider)>,
+    config: &Config,
+) -> Result<(), Failure> {
+    let stop = AtomicBool::new(false);
+    let with_server = servers.iter().any(|(server, _)| server.is_some());
+    let mut columns = vec!["time", "id", "duration", "client", "client_name", "command"];
+    if with_server {
+        columns.insert(0, "server")
+    }
+    while !stopped(&stop) {
+        let mut records = Vec::new();
+        let result = for_each_server(servers.clone(), |server, con_provider| {
+            for r in get_records(con_provider, server.as_deref(), config.since_id, config)? {
+                records.push((server.clone(), r))
+            }
+            Ok(())
+        });
+        let error = match result {
+            Err(Failure::Redis(e @ RslogError::Auth { .. })) => return Err(e.into()),
+            Err(Failure::Redis(e)) => format!("\n{}\n", e),
+            Err(e) => return Err(e),
+            Ok(()) => String::new(),
+        };
+        records.sort_by_key(|(_, r)| (r.time, r.id));
+        if config.newest_first {
+            records.reverse()
+        }
+        let rows: Vec<_> = records
+            .iter()
+            .map(|(server, r)| watch_row(r, server.as_deref(), config))
+            .collect();
+        let screen = format!(
+            "{}{} records, every {:?}\n\n{}{}",
+            watch::CLEAR,
+            records.len(),
+            config.interval,
+            watch::render_table(&columns, &rows, config.header),
+            error
+        );
+        out.write(&screen)?;
+        out.flush()?;
+        sleep(config.interval, &stop)
+    }
+    Ok(())
+}
+
+// Follows every server in its own thread and prints records from all of them
+fn read_continiously(
+    out: &mut Output,
+    servers: Vec<(Option<String>, RedisConnectionProvider)>,
+    config: &Config,
+) -> Result<(), Failure> {
+    let (tx, rx) = mpsc::channel();
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        for (server, con_provider) in servers {
+            let tx = tx.clone();
+            let stop = &stop;
+            s.spawn(move || {
+                let result = follow(
+                    con_provider,
+                    server.as_deref(),
+                    config,
+                    stop,
+                    |records, last_id| {
+                        let _ = tx.send(Ok((server.clone(), records, last_id)));
+                    },
+                );
+                if let Err(e) = result {
+                    let _ = tx.send(Err(e));
+                }
+            });
+        }
+        drop(tx);
+        let mut state = FollowState::new(config);
+        let result = loop {
+            // waits in short steps so the status is logged while servers are unavailable too
+            let written = match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(Ok((server, records, last_id))) => {
+                    print_new_records(out, records, server.as_deref(), config, &mut state)
+                        .map(|()| state.save_checkpoint(last_id, config))
                 }
+                Ok(Err(e)) => break Err(e.into()),
+                Err(mpsc::RecvTimeoutError::Timeout) => Ok(()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break Ok(()),
+            };
+            state.report_status(config);
+            if let Err(e) = written.and_then(|()| state.heartbeat(out, config)) {
+                break Err(e.into());
             }
+        };
+        // the other servers are stopped when one of them or the output failed
+        stop.store(true, Ordering::Relaxed);
+        finish_following(out, config, &mut state)?;
+        result
+    })
+}
+
+fn discover_cluster(seed: &RedisConnectionProvider) -> Result<Vec<(String, u16)>, RslogError> {
+    seed.get_connection()
+        .and_then(|mut con| rsloglib::cluster_masters(&mut con))
+        .context("Can't read cluster topology")
+}
+
+fn cluster_node_provider(host: &str, port: u16, config: &Config) -> RedisConnectionProvider {
+    connection_provider(
+        connection_info(server_addr(host, port, config), config),
+        config,
+    )
+}
+
+fn cluster_servers(
+    seed: RedisConnectionProvider,
+    config: &Config,
+) -> Result<Vec<(Option<String>, RedisConnectionProvider)>, RslogError> {
+    Ok(discover_cluster(&seed)?
+        .into_iter()
+        .map(|(host, port)| {
+            (
+                Some(format!("{}:{}", host, port)),
+                cluster_node_provider(&host, port, config),
+            )
+        })
+        .collect())
+}
+
+// Follows all cluster masters, topology is re-read periodically to pick up added and removed nodes
+fn read_cluster_continiously(
+    out: &mut Output,
+    seed: RedisConnectionProvider,
+    config: &Config,
+) -> Result<(), Failure> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|s| {
+        let mut nodes: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+        let mut state = FollowState::new(config);
+        let stop_all = |nodes: &HashMap<String, Arc<AtomicBool>>| {
+            nodes
+                .values()
+                .for_each(|stop| stop.store(true, Ordering::Relaxed))
+        };
+        let result = 'following: loop {
+            if SHUTDOWN.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            match discover_cluster(&seed) {
+                Err(e) => {
+                    if let Err(e) = handle_error(e, config) {
+                        break Err(e.into());
+                    }
+                }
+                Ok(masters) => {
+                    let masters: HashMap<_, _> = masters
+                        .into_iter()
+                        .map(|(host, port)| (format!("{}:{}", host, port), (host, port)))
+                        .collect();
+                    nodes.retain(|server, stop| {
+                        let keep = masters.contains_key(server);
+                        if !keep {
+                            log::info!("Node {} left the cluster", server);
+                            stop.store(true, Ordering::Relaxed)
+                        }
+                        keep
+                    });
+                    for (server, (host, port)) in masters {
+                        if nodes.contains_key(&server) {
+                            continue;
+                        }
+                        log::info!("Following cluster node {}", server);
+                        let stop = Arc::new(AtomicBool::new(false));
+                        nodes.insert(server.clone(), stop.clone());
+                        let con_provider = cluster_node_provider(&host, port, config);
+                        let tx = tx.clone();
+                        s.spawn(move || {
+                            let result =
+                                follow(con_provider, Some(&server), config, &stop, |records, _| {
+                                    let _ = tx.send(Ok((server.clone(), records)));
+                                });
+                            if let Err(e) = result {
+                                let _ = tx.send(Err(e));
+                            }
+                        });
+                    }
+                }
+            }
+            let deadline = Instant::now() + Duration::from_secs(config.topology_interval);
+            while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+                if SHUTDOWN.load(Ordering::Relaxed) {
+                    break;
+                }
+                let written = match rx.recv_timeout(left.min(Duration::from_millis(100))) {
+                    Ok(Ok((server, records))) => {
+                        print_new_records(out, records, Some(&server), config, &mut state)
+                    }
+                    Ok(Err(e)) => break 'following Err(e.into()),
+                    Err(_) => Ok(()),
+                };
+                state.report_status(config);
+                if let Err(e) = written.and_then(|()| state.heartbeat(out, config)) {
+                    break 'following Err(e.into());
+                }
+            }
+        };
+        stop_all(&nodes);
+        finish_following(out, config, &mut state)?;
+        result
+    })
+}
+
+fn server_addr(host: &str, port: u16, config: &Config) -> redis::ConnectionAddr {
+    if config.tls {
+        redis::ConnectionAddr::TcpTls {
+            host: host.to_owned(),
+            port,
+            insecure: config.insecure,
         }
-        sleep(Duration::new(config.interval, 0));
+    } else {
+        redis::ConnectionAddr::Tcp(host.to_owned(), port)
     }
 }
 
-pub fn main() {
-    let config = argument_parsing::get_config()
-        .map_err(|e| e.exit())
-        .unwrap();
-    stderrlog::new()
-        .timestamp(stderrlog::Timestamp::Second)
-        .verbosity(config.verbosity)
-        .quiet(config.quiet)
-        .init()
-        .unwrap();
-    let redis_client = redis::Client::open(redis::ConnectionInfo {
-        addr: Box::new(redis::ConnectionAddr::Tcp(
-            config.hostname.clone(),
-            config.port,
+fn connection_info(addr: redis::ConnectionAddr, config: &Config) -> redis::ConnectionInfo {
+    redis::ConnectionInfo {
+        addr: Box::new(addr),
+        db: config.db,
+        username: config.username.clone(),
+        // redis only sends AUTH when there is a password, so let the server reject a user without one
+        passwd: config
+            .password
+            .clone()
+            .or_else(|| config.username.as_ref().map(|_| String::new())),
+    }
+}
+
+fn connection_provider(
+    connection_info: redis::ConnectionInfo,
+    config: &Config,
+) -> RedisConnectionProvider {
+    // no single connection or command outlasts --total-timeout
+    let connect_timeout = config
+        .total_timeout
+        .map_or(config.connect_timeout, |total| {
+            config.connect_timeout.min(total.as_secs().max(1))
+        });
+    let read_timeout = match (config.read_timeout, config.total_timeout) {
+        (0, total) => total,
+        (read, total) => Some(Duration::from_secs(read).min(total.unwrap_or(Duration::MAX))),
+    };
+    let mut connection_provider = match &config.master_name {
+        Some(master_name) => RedisConnectionProvider::from((
+            Sentinel::new(
+                config.sentinels.clone(),
+                master_name.clone(),
+                connection_info,
+            ),
+            connect_timeout,
         )),
-        db: 0,
-        username: None,
-        passwd: config.password.clone(),
-    })
-    .unwrap();
-    let connection_provider = RedisConnectionProvider::from((redis_client, config.interval));
-    if config.follow {
-        read_continiously(connection_provider, &config)
+        None => RedisConnectionProvider::from((connection_info, connect_timeout)),
+    };
+    if let Some(command) = &config.password_command {
+        let command = command.clone();
+        connection_provider.set_password_callback(Some(Arc::new(move || {
+            argument_parsing::run_password_command(&command)
+        })))
+    }
+    connection_provider.set_read_timeout(read_timeout);
+    connection_provider
+}
+
+pub fn main() {
+    let config = argument_parsing::get_config().unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            // --help and --version
+            e.exit()
+        }
+        eprint!("{}", e);
+        std::process::exit(EXIT_CONFIG)
+    });
+    if let Some(ca_cert) = &config.tls_ca_cert {
+        // redis 0.19 can't be given root certificates, but native-tls with OpenSSL reads them
+        // from the environment. It's set before any thread is spawned, as setting it later
+        // would race with threads reading the environment.
+        std::env::set_var("SSL_CERT_FILE", ca_cert)
+    }
+    if config.json_log {
+        json_log::JsonLogger::init(config.verbosity, config.quiet).unwrap()
+    } else {
+        stderrlog::new()
+            .timestamp(stderrlog::Timestamp::Second)
+            .verbosity(config.verbosity)
+            .quiet(config.quiet)
+            .init()
+            .unwrap()
+    }
+    for setting in &config.ignored_settings {
+        log::warn!(
+            "Ignoring {}, it conflicts with a setting of higher precedence",
+            setting
+        )
+    }
+    if config.pretty && !matches!(config.output_format, OutputFormat::Json) {
+        log::warn!("--pretty has no effect for non JSON output formats")
+    }
+    if config.compact_json && !matches!(config.output_format, OutputFormat::Json) {
+        log::warn!("--compact-json has no effect for non JSON output formats")
+    }
+    if let Some(timeout) = config.total_timeout {
+        // reading stops after the server being read and main closes the output and restores
+        // slowlog settings as usual. Redis calls are bounded by the timeout too, see
+        // `connection_provider`, so the process is only ended here if main still hangs after that.
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            let _ = TIMED_OUT.set(timeout);
+            std::thread::sleep(TOTAL_TIMEOUT_GRACE);
+            log::error!("Gave up after the total timeout of {:?}", timeout);
+            std::process::exit(EXIT_CONNECTION)
+        });
+    }
+    let servers: Vec<_> = if config.servers.is_empty() {
+        let addr = match &config.socket {
+            Some(socket) => redis::ConnectionAddr::Unix(socket.into()),
+            None => server_addr(&config.hostname, config.port, &config),
+        };
+        let source = match (&config.socket, &config.master_name) {
+            (Some(socket), _) => socket.clone(),
+            (None, Some(master_name)) => master_name.clone(),
+            (None, None) => format!("{}:{}", config.hostname, config.port),
+        };
+        vec![(
+            config.with_source.then_some(source),
+            connection_provider(connection_info(addr, &config), &config),
+        )]
+    } else {
+        config
+            .servers
+            .iter()
+            .map(|(host, port)| {
+                let addr = server_addr(host, *port, &config);
+                (
+                    Some(format!("{}:{}", host, port)),
+                    connection_provider(connection_info(addr, &config), &config),
+                )
+            })
+            .collect()
+    };
+    if config.check {
+        let mut code = 0;
+        for (server, con_provider) in servers {
+            if let Err(e) = check(con_provider, server.as_deref()) {
+                code = exit_code(&e)
+            }
+        }
+        std::process::exit(code)
+    }
+    if config.server_info {
+        println!("rslog {}", clap::crate_version!());
+        let mut code = 0;
+        for (server, con_provider) in servers {
+            if let Err(e) = print_server_info(con_provider, server.as_deref()) {
+                error_handler(&e);
+                code = exit_code(&e)
+            }
+        }
+        std::process::exit(code)
+    }
+    if config.len {
+        print_len(servers[0].1.clone()).unwrap_or_else(|e| exit_on_error(e));
+        std::process::exit(0)
+    }
+    if config.reset {
+        reset(servers[0].1.clone()).unwrap_or_else(|e| exit_on_error(e));
+        std::process::exit(0)
+    }
+    if let Some(addr) = &config.metrics_addr {
+        match std::net::TcpListener::bind(addr) {
+            Ok(listener) => METRICS.get_or_init(Metrics::default).serve(listener),
+            Err(e) => {
+                log::error!("Can't listen for metrics on {}: {}", addr, e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    if let Some(addr) = &config.statsd {
+        match Statsd::connect(addr, config.statsd_prefix.clone()) {
+            Ok(statsd) => {
+                let _ = STATSD.set(statsd);
+            }
+            Err(e) => {
+                log::error!("Can't send metrics to statsd at {}: {}", addr, e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    if config.watch && !std::io::stdout().is_terminal() {
+        log::error!("--watch needs a terminal");
+        std::process::exit(EXIT_CONFIG)
+    }
+    if config.follow || config.watch || config.new_only {
+        ctrlc::set_handler(|| SHUTDOWN.store(true, Ordering::Relaxed)).unwrap();
+    }
+    let mut out = Output::default();
+    if config.output_file.is_none() || config.tee {
+        out.add(Stdout::new())
+    }
+    if let Some(path) = &config.output_file {
+        match RotatingFile::open(path, config.rotate_size, config.rotate_count) {
+            Ok(file) => out.add(file),
+            Err(e) => {
+                log::error!("Can't open output file {}: {}", path.display(), e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    if let Some((facility, severity)) = config.syslog {
+        match Syslog::connect(facility, severity) {
+            Ok(syslog) => out.add(syslog),
+            Err(e) => {
+                log::error!("Can't connect to syslog: {}", e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    if let Some(url) = &config.webhook {
+        out.add(Webhook::start(
+            url.clone(),
+            config.webhook_batch_size,
+            Duration::from_millis(config.webhook_flush_interval),
+            config.webhook_retries,
+        ))
+    }
+    if let Some(addr) = &config.serve {
+        match std::net::TcpListener::bind(addr) {
+            Ok(listener) => out.add(Broadcast::start(listener)),
+            Err(e) => {
+                log::error!("Can't listen for subscribers on {}: {}", addr, e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    #[cfg(feature = "kafka")]
+    if let Some((brokers, topic)) = &config.kafka {
+        match kafka::Kafka::connect(brokers, topic.clone()) {
+            Ok(kafka) => out.add(kafka),
+            Err(e) => {
+                log::error!("Can't create Kafka producer: {}", e);
+                std::process::exit(EXIT_FAILURE)
+            }
+        }
+    }
+    #[cfg(not(feature = "kafka"))]
+    if config.kafka.is_some() {
+        log::error!("rslog was built without Kafka support, rebuild it with --features kafka");
+        std::process::exit(EXIT_CONFIG)
+    }
+    // changed only once the sinks are ready, so failing to open one leaves the servers as they were
+    let previous_config = if config.set_threshold.is_some() || config.set_max_len.is_some() {
+        configure_slowlog(&servers, &config).unwrap_or_else(|e| {
+            let _ = out.close();
+            exit_on_error(e)
+        })
     } else {
-        read_once(connection_provider, &config)
+        Vec::new()
+    };
+    let header = if config.header
+        && !config.aggregate
+        && config.count_by_client.is_none()
+        && !config.stats
+        && !config.watch
+    {
+        print_header(
+            &mut out,
+            &config,
+            config.cluster || !config.servers.is_empty() || config.with_source,
+        )
+    } else {
+        Ok(())
+    };
+    // the number of records is only known when reading once
+    let result = if let Err(e) = header {
+        Err(e.into())
+    } else if config.watch {
+        watch(&mut out, servers, &config).map(|()| None)
+    } else if config.follow {
+        if config.cluster {
+            read_cluster_continiously(&mut out, servers.into_iter().next().unwrap().1, &config)
+        } else {
+            read_continiously(&mut out, servers, &config)
+        }
+        .map(|()| None)
+    } else {
+        read_all_once(&mut out, servers, &config).map(Some)
+    };
+    let closed = out.close();
+    // put back before exiting on errors too
+    if config.restore_config {
+        restore_slowlog(previous_config, &config)
+    }
+    let found = result.unwrap_or_else(|e| exit_on_failure(e));
+    if let Err(e) = closed {
+        exit_on_failure(e.into())
+    }
+    if let (Some(0), Some(code)) = (found, config.once_empty_exit_code) {
+        std::process::exit(code)
     }
 }
@@ -0,0 +1,197 @@
+use rsloglib::SlowlogRecord;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Upper bounds of duration histogram buckets in microseconds
+const BUCKETS: [u64; 9] = [
+    1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000, 10_000_000,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: u64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct State {
+    // keyed by command name, sorted so the output is stable
+    commands: BTreeMap<String, Histogram>,
+    reconnects: u64,
+}
+
+/// Counters exported in Prometheus text format
+#[derive(Default)]
+pub struct Metrics(Mutex<State>);
+
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn observe(&self, records: &[SlowlogRecord]) {
+        let mut state = self.0.lock().unwrap();
+        for r in records {
+            let command = r
+                .command
+                .first()
+                .map_or(String::new(), |c| c.to_lowercase());
+            let histogram = state.commands.entry(command).or_default();
+            let duration = r.duration;
+            for (bucket, le) in histogram.buckets.iter_mut().zip(BUCKETS.iter()) {
+                if duration <= *le {
+                    *bucket += 1
+                }
+            }
+            histogram.sum = histogram.sum.saturating_add(duration);
+            histogram.count += 1;
+        }
+    }
+
+    pub fn reconnected(&self) {
+        self.0.lock().unwrap().reconnects += 1
+    }
+
+    fn render(&self) -> String {
+        let state = self.0.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP rslog_records_total Number of slowlog records read.\n");
+        out.push_str("# TYPE rslog_records_total counter\n");
+        for (command, h) in &state.commands {
+            let _ = writeln!(
+                out,
+                "rslog_records_total{{command=\"{}\"}} {}",
+                label_value(command),
+                h.count
+            );
+        }
+        out.push_str("# HELP rslog_slow_duration_microseconds Duration of slowlog records.\n");
+        out.push_str("# TYPE rslog_slow_duration_microseconds histogram\n");
+        for (command, h) in &state.commands {
+            let command = label_value(command);
+            for (count, le) in h.buckets.iter().zip(BUCKETS.iter()) {
+                let _ = writeln!(
+                    out,
+                    "rslog_slow_duration_microseconds_bucket{{command=\"{}\",le=\"{}\"}} {}",
+                    command, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rslog_slow_duration_microseconds_bucket{{command=\"{}\",le=\"+Inf\"}} {}",
+                command, h.count
+            );
+            let _ = writeln!(
+                out,
+                "rslog_slow_duration_microseconds_sum{{command=\"{}\"}} {}",
+                command, h.sum
+            );
+            let _ = writeln!(
+                out,
+                "rslog_slow_duration_microseconds_count{{command=\"{}\"}} {}",
+                command, h.count
+            );
+        }
+        out.push_str("# HELP rslog_reconnects_total Number of reconnections to redis servers.\n");
+        out.push_str("# TYPE rslog_reconnects_total counter\n");
+        let _ = writeln!(out, "rslog_reconnects_total {}", state.reconnects);
+        out
+    }
+
+    fn respond(&self, stream: TcpStream) -> std::io::Result<()> {
+        // scrapes are served one by one, so a stuck client must not block the others
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // the rest of the request doesn't matter, but has to be read before answering
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear()
+        }
+        let mut stream = reader.into_inner();
+        match request_line.split_whitespace().nth(1) {
+            Some("/metrics") | Some("/") => {
+                let body = self.render();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            ),
+        }
+    }
+
+    /// Answers scrapes on `listener` until the process exits
+    pub fn serve(&'static self, listener: TcpListener) {
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Err(e) = stream.and_then(|s| self.respond(s)) {
+                    log::warn!("Can't answer metrics request: {}", e)
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.observe(&[
+            SlowlogRecord {
+                duration: 3000,
+                command: vec!["GET".to_owned(), "key".to_owned()],
+                ..Default::default()
+            },
+            SlowlogRecord {
+                duration: 20000,
+                command: vec!["get".to_owned(), "key".to_owned()],
+                ..Default::default()
+            },
+        ]);
+        metrics.reconnected();
+        let out = metrics.render();
+        assert!(out.contains("rslog_records_total{command=\"get\"} 2\n"));
+        assert!(out
+            .contains("rslog_slow_duration_microseconds_bucket{command=\"get\",le=\"1000\"} 0\n"));
+        assert!(out
+            .contains("rslog_slow_duration_microseconds_bucket{command=\"get\",le=\"5000\"} 1\n"));
+        assert!(out
+            .contains("rslog_slow_duration_microseconds_bucket{command=\"get\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("rslog_slow_duration_microseconds_sum{command=\"get\"} 23000\n"));
+        assert!(out.contains("rslog_reconnects_total 1\n"));
+    }
+
+    #[test]
+    fn huge_durations() {
+        let metrics = Metrics::default();
+        let record = SlowlogRecord {
+            duration: i64::MAX as u64,
+            command: vec!["GET".to_owned()],
+            ..Default::default()
+        };
+        metrics.observe(&[record.clone(), record.clone(), record]);
+        let sum = format!(
+            "rslog_slow_duration_microseconds_sum{{command=\"get\"}} {}\n",
+            u64::MAX
+        );
+        assert!(metrics.render().contains(&sum));
+    }
+}
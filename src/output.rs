@@ -0,0 +1,458 @@
+use rsloglib::SlowlogRecord;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use syslog::LogFormat;
+
+/// A record as it is handed to every sink
+// `record` and `server` are only read by the Kafka sink for now
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+pub struct Entry<'a> {
+    pub record: &'a SlowlogRecord,
+    pub server: Option<&'a str>,
+    /// The record in the chosen output format, without a line break
+    pub line: &'a str,
+    /// The record with its tags as JSON, only built when a sink wants it
+    pub json: Option<&'a serde_json::Value>,
+}
+
+/// Destination of records, every sink of the output receives every record.
+/// Heartbeats go to every sink too, reports and other lines that aren't records only go to stdout and files.
+pub trait Sink {
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        self.write_line(entry.line)
+    }
+
+    fn write_line(&mut self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes a heartbeat, `line` is in the output format and `json` is sent by sinks that stream JSON
+    fn heartbeat(&mut self, line: &str, _json: &serde_json::Value) -> io::Result<()> {
+        self.write_line(line)
+    }
+
+    /// Writes text as is, like headers and tables, only stdout and files write it
+    fn write_text(&mut self, _text: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Waits until everything written is delivered, needed before exiting the process
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    // Whether `json` of the entries is used
+    fn wants_json(&self) -> bool {
+        false
+    }
+}
+
+/// Appends to a file and moves it to `<path>.1` once it grows over `rotate_size` bytes,
+/// older files are shifted to `.2`, `.3` and so on, keeping at most `keep` of them
+pub struct RotatingFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    rotate_size: u64,
+    keep: u32,
+}
+
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    name.into()
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, rotate_size: u64, keep: u32) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RotatingFile {
+            path: path.to_owned(),
+            size: file.metadata()?.len(),
+            file: BufWriter::new(file),
+            rotate_size,
+            keep,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        log::debug!("Rotating {}", self.path.display());
+        self.file.flush()?;
+        for n in (1..self.keep).rev() {
+            let from = backup_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, backup_path(&self.path, n + 1))?
+            }
+        }
+        if self.keep > 0 {
+            std::fs::rename(&self.path, backup_path(&self.path, 1))?;
+        }
+        self.file = BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&self.path)?,
+        );
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Writes the whole record to one file, rotating before it if needed
+    fn write_record(&mut self, record: &str) -> io::Result<()> {
+        if self.rotate_size > 0
+            && self.size > 0
+            && self.size + record.len() as u64 > self.rotate_size
+        {
+            self.rotate()?
+        }
+        self.file.write_all(record.as_bytes())?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+}
+
+impl Sink for RotatingFile {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.write_record(&format!("{}\n", line))
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.write_record(text)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Stdout and files are buffered and flushed after every batch of records, or after every record with --flush
+pub struct Stdout(BufWriter<io::Stdout>);
+
+impl Stdout {
+    pub fn new() -> Stdout {
+        Stdout(BufWriter::new(io::stdout()))
+    }
+}
+
+impl Sink for Stdout {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.0, "{}", line)
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Sends records to the local syslog daemon with a fixed severity
+pub struct Syslog {
+    logger: syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>,
+    severity: syslog::Severity,
+}
+
+impl Syslog {
+    pub fn connect(
+        facility: syslog::Facility,
+        severity: syslog::Severity,
+    ) -> syslog::Result<Syslog> {
+        let formatter = syslog::Formatter3164 {
+            facility,
+            process: "rslog".to_owned(),
+            ..Default::default()
+        };
+        Ok(Syslog {
+            logger: syslog::unix(formatter)?,
+            severity,
+        })
+    }
+}
+
+impl Syslog {
+    fn send(&mut self, line: &str) {
+        let logger = &mut self.logger;
+        // losing a record in syslog shouldn't stop the other sinks
+        if let Err(e) = logger
+            .formatter
+            .format(&mut logger.backend, self.severity, line)
+        {
+            log::warn!("Can't send record to syslog: {}", e)
+        }
+    }
+}
+
+// Reports aren't sent, syslog only gets records and heartbeats
+impl Sink for Syslog {
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        self.send(entry.line);
+        Ok(())
+    }
+
+    fn heartbeat(&mut self, line: &str, _json: &serde_json::Value) -> io::Result<()> {
+        self.send(line);
+        Ok(())
+    }
+}
+
+/// Posts records to an HTTP endpoint as JSON arrays from a background thread, so a slow or
+/// unavailable endpoint doesn't hold up reading the slowlog
+pub struct Webhook {
+    sender: Option<mpsc::Sender<serde_json::Value>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// Tries to deliver a batch `retries` more times after a failure, doubling the delay every time
+fn post(url: &str, batch: &[serde_json::Value], retries: u32) {
+    let mut delay = Duration::from_millis(500);
+    for attempt in 0..=retries {
+        match ureq::post(url).send_json(batch) {
+            Ok(_) => return,
+            Err(e) => log::warn!("Webhook request to {} failed: {}", url, e),
+        }
+        if attempt < retries {
+            std::thread::sleep(delay);
+            delay *= 2
+        }
+    }
+    log::error!(
+        "Dropping {} records after {} failed webhook requests",
+        batch.len(),
+        retries + 1
+    )
+}
+
+impl Webhook {
+    /// Sends a batch once it has `batch_size` records or `flush_interval` has passed since the last one
+    pub fn start(
+        url: String,
+        batch_size: usize,
+        flush_interval: Duration,
+        retries: u32,
+    ) -> Webhook {
+        let (sender, receiver) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut batch = Vec::new();
+            let mut deadline = Instant::now() + flush_interval;
+            loop {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(record) => {
+                        batch.push(record);
+                        if batch.len() < batch_size {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if !batch.is_empty() {
+                    post(&url, &batch, retries);
+                    batch.clear()
+                }
+                deadline = Instant::now() + flush_interval;
+            }
+            if !batch.is_empty() {
+                post(&url, &batch, retries)
+            }
+        });
+        Webhook {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, record: serde_json::Value) {
+        if let Some(sender) = &self.sender {
+            sender.send(record).unwrap()
+        }
+    }
+}
+
+impl Sink for Webhook {
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if let Some(json) = entry.json {
+            self.send(json.clone())
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&mut self, _line: &str, json: &serde_json::Value) -> io::Result<()> {
+        self.send(json.clone());
+        Ok(())
+    }
+
+    /// Sends the records left in the batch and waits until they are delivered
+    fn close(&mut self) -> io::Result<()> {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap()
+        }
+        Ok(())
+    }
+
+    fn wants_json(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+// Records waiting to be written to a subscriber before it is considered too slow
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+// Address of a subscriber and the queue of frames for it
+type Subscriber = (String, SyncSender<Arc<Vec<u8>>>);
+
+/// Streams records to everyone connected to a TCP address, every record is a JSON object
+/// preceded by its length as a 4 byte big endian integer. Subscribers that can't keep up
+/// are disconnected instead of slowing down reading the slowlog.
+pub struct Broadcast {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+fn stream_to(mut stream: TcpStream, frames: mpsc::Receiver<Arc<Vec<u8>>>) -> io::Result<()> {
+    for frame in frames {
+        stream.write_all(&frame)?
+    }
+    Ok(())
+}
+
+impl Broadcast {
+    /// Accepts subscribers on `listener` from a background thread
+    pub fn start(listener: TcpListener) -> Broadcast {
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let accepted = subscribers.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("Can't accept subscriber: {}", e);
+                        continue;
+                    }
+                };
+                let peer = stream
+                    .peer_addr()
+                    .map_or("unknown".to_owned(), |a| a.to_string());
+                log::info!("Subscriber {} connected", peer);
+                let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_QUEUE);
+                accepted.lock().unwrap().push((peer.clone(), sender));
+                std::thread::spawn(move || {
+                    if let Err(e) = stream_to(stream, receiver) {
+                        log::info!("Subscriber {} disconnected: {}", peer, e)
+                    }
+                });
+            }
+        });
+        Broadcast { subscribers }
+    }
+
+    pub fn send(&self, record: &serde_json::Value) {
+        let json = serde_json::to_vec(record).unwrap();
+        let mut frame = Vec::with_capacity(json.len() + 4);
+        frame.extend_from_slice(&(json.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&json);
+        let frame = Arc::new(frame);
+        self.subscribers.lock().unwrap().retain(|(peer, sender)| {
+            match sender.try_send(frame.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::warn!("Disconnecting subscriber {} that can't keep up", peer);
+                    false
+                }
+                // the connection was closed
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        })
+    }
+}
+
+impl Sink for Broadcast {
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if let Some(json) = entry.json {
+            self.send(json)
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&mut self, _line: &str, json: &serde_json::Value) -> io::Result<()> {
+        self.send(json);
+        Ok(())
+    }
+
+    fn wants_json(&self) -> bool {
+        true
+    }
+}
+
+/// All sinks records are written to, in the order they were added
+#[derive(Default)]
+pub struct Output {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Output {
+    pub fn add(&mut self, sink: impl Sink + 'static) {
+        self.sinks.push(Box::new(sink))
+    }
+
+    /// Writes text as is to stdout and files
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        self.sinks.iter_mut().try_for_each(|s| s.write_text(text))
+    }
+
+    /// Writes a line that isn't a record, like a report
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.sinks.iter_mut().try_for_each(|s| s.write_line(line))
+    }
+
+    pub fn heartbeat(&mut self, line: &str, json: &serde_json::Value) -> io::Result<()> {
+        self.sinks
+            .iter_mut()
+            .try_for_each(|s| s.heartbeat(line, json))
+    }
+
+    pub fn write_record(&mut self, entry: &Entry) -> io::Result<()> {
+        self.sinks.iter_mut().try_for_each(|s| s.write(entry))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sinks.iter_mut().try_for_each(|s| s.flush())
+    }
+
+    // Whether records need to be converted to JSON values for any of the sinks
+    pub fn streams_json(&self) -> bool {
+        self.sinks.iter().any(|s| s.wants_json())
+    }
+
+    /// Flushes all sinks and waits for pending webhook requests and Kafka messages,
+    /// needed before exiting the process. Every sink is closed even if one fails,
+    /// the first error is returned.
+    pub fn close(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            let closed = sink.close();
+            if result.is_ok() {
+                result = closed
+            }
+        }
+        result
+    }
+}
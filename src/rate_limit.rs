@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket that lets through `rate` records per second on average,
+/// with bursts of up to a second worth of records
+pub struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> RateLimiter {
+        RateLimiter {
+            rate,
+            tokens: rate.max(1.0),
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.last = now;
+    }
+
+    // Takes a token if there is one, otherwise returns how long to wait for it
+    pub fn take_at(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    pub fn take(&mut self) -> Result<(), Duration> {
+        self.take_at(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(2.0);
+        limiter.last = start;
+        assert_eq!(limiter.take_at(start), Ok(()));
+        assert_eq!(limiter.take_at(start), Ok(()));
+        assert_eq!(limiter.take_at(start), Err(Duration::from_millis(500)));
+        assert_eq!(limiter.take_at(start + Duration::from_millis(500)), Ok(()));
+        // no more than a second worth of tokens is saved up
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.take_at(later), Ok(()));
+        assert_eq!(limiter.take_at(later), Ok(()));
+        assert!(limiter.take_at(later).is_err());
+    }
+}
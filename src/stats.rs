@@ -0,0 +1,112 @@
+use rsloglib::SlowlogRecord;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DurationStats {
+    pub count: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+}
+
+// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], p: usize) -> u64 {
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+pub fn duration_stats(mut durations: Vec<u64>) -> DurationStats {
+    if durations.is_empty() {
+        return DurationStats::default();
+    }
+    durations.sort_unstable();
+    DurationStats {
+        count: durations.len(),
+        min: durations[0],
+        max: durations[durations.len() - 1],
+        // summed in u128, durations up to i64::MAX would overflow u64
+        mean: durations.iter().map(|&d| d as u128).sum::<u128>() as f64 / durations.len() as f64,
+        p50: percentile(&durations, 50),
+        p95: percentile(&durations, 95),
+        p99: percentile(&durations, 99),
+    }
+}
+
+impl DurationStats {
+    pub fn render_text(&self) -> String {
+        format!(
+            "count: {}, min: {}, max: {}, mean: {:.1}, p50: {}, p95: {}, p99: {}",
+            self.count, self.min, self.max, self.mean, self.p50, self.p95, self.p99
+        )
+    }
+}
+
+/// Durations of records read during the last `length` while following
+pub struct Window {
+    length: Duration,
+    durations: VecDeque<(Instant, u64)>,
+}
+
+impl Window {
+    pub fn new(length: Duration) -> Window {
+        Window {
+            length,
+            durations: VecDeque::new(),
+        }
+    }
+
+    /// Adds new records and forgets the ones that left the window, returns whether anything changed
+    pub fn update(&mut self, records: &[SlowlogRecord]) -> bool {
+        let now = Instant::now();
+        let before = self.durations.len();
+        while matches!(self.durations.front(), Some((t, _)) if now.duration_since(*t) > self.length)
+        {
+            self.durations.pop_front();
+        }
+        let expired = before != self.durations.len();
+        self.durations
+            .extend(records.iter().map(|r| (now, r.duration)));
+        expired || !records.is_empty()
+    }
+
+    pub fn stats(&self) -> DurationStats {
+        duration_stats(self.durations.iter().map(|(_, d)| *d).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let stats = duration_stats((1..=100).rev().collect());
+        assert_eq!(
+            stats,
+            DurationStats {
+                count: 100,
+                min: 1,
+                max: 100,
+                mean: 50.5,
+                p50: 50,
+                p95: 95,
+                p99: 99,
+            }
+        );
+        assert_eq!(duration_stats(vec![7]).p99, 7);
+        assert_eq!(duration_stats(Vec::new()), DurationStats::default());
+    }
+
+    #[test]
+    fn huge_durations() {
+        let max = i64::MAX as u64;
+        let stats = duration_stats(vec![max, max, max]);
+        assert_eq!((stats.max, stats.p50), (max, max));
+        assert_eq!(stats.mean, max as f64);
+    }
+}
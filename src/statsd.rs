@@ -0,0 +1,93 @@
+use rsloglib::SlowlogRecord;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Pushes a timing and a counter for every record to a StatsD server
+pub struct Statsd {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+// Keeps command names usable as a part of a metric name
+fn metric_name(command: &str) -> String {
+    command
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn packet(prefix: &str, r: &SlowlogRecord) -> String {
+    let command = metric_name(r.command.first().map_or("", |c| c.as_str()));
+    format!(
+        "{prefix}.duration.{command}:{}|ms\n{prefix}.commands.{command}:1|c",
+        r.duration as f64 / 1000.0,
+        prefix = prefix,
+        command = command
+    )
+}
+
+// Any local port of the address family of the server, an IPv4 socket can't send to IPv6 servers
+fn local_addr(server: &SocketAddr) -> SocketAddr {
+    match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+impl Statsd {
+    /// Tries every address `addr` resolves to until a socket can be connected to one of them
+    pub fn connect(addr: &str, prefix: String) -> std::io::Result<Statsd> {
+        let mut last_error = None;
+        for server in addr.to_socket_addrs()? {
+            match UdpSocket::bind(local_addr(&server)).and_then(|s| s.connect(server).map(|_| s)) {
+                Ok(socket) => {
+                    // a full buffer drops metrics instead of stalling slowlog reading
+                    socket.set_nonblocking(true)?;
+                    return Ok(Statsd { socket, prefix });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} doesn't resolve to any address", addr),
+            )
+        }))
+    }
+
+    pub fn send(&self, records: &[SlowlogRecord]) {
+        for r in records {
+            if let Err(e) = self.socket.send(packet(&self.prefix, r).as_bytes()) {
+                log::debug!("Can't send metrics to statsd: {}", e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let r = SlowlogRecord {
+            duration: 1500,
+            command: vec!["CLIENT".to_owned(), "LIST".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            packet("rslog", &r),
+            "rslog.duration.client:1.5|ms\nrslog.commands.client:1|c"
+        );
+        assert_eq!(metric_name("my.Cmd:x"), "my_cmd_x");
+    }
+
+    #[test]
+    fn address_family() {
+        let v4 = local_addr(&"127.0.0.1:8125".parse().unwrap());
+        let v6 = local_addr(&"[::1]:8125".parse().unwrap());
+        assert_eq!(v4, "0.0.0.0:0".parse().unwrap());
+        assert_eq!(v6, "[::]:0".parse().unwrap());
+    }
+}
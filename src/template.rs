@@ -0,0 +1,120 @@
+use rsloglib::SlowlogRecord;
+
+enum Field {
+    Time,
+    Id,
+    Duration,
+    ClientSocket,
+    ClientName,
+    Command,
+    Server,
+    Count,
+}
+
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+pub struct Template(Vec<Segment>);
+
+impl Field {
+    fn from_name(name: &str) -> Result<Field, String> {
+        Ok(match name {
+            "time" => Field::Time,
+            "id" => Field::Id,
+            "duration" => Field::Duration,
+            "client_socket" => Field::ClientSocket,
+            "client_name" => Field::ClientName,
+            "command" => Field::Command,
+            "server" => Field::Server,
+            "count" => Field::Count,
+            _ => return Err(format!("Unknown field in template: {}", name)),
+        })
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{')
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}')
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("Unclosed '{' in template".to_owned()),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)))
+                    }
+                    segments.push(Segment::Field(Field::from_name(&name)?))
+                }
+                '}' => return Err("Unmatched '}' in template, use '}}' to print it".to_owned()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal))
+        }
+        Ok(Template(segments))
+    }
+
+    pub fn render(&self, r: &SlowlogRecord, time: &str, server: &str, count: u64) -> String {
+        self.0
+            .iter()
+            .map(|s| match s {
+                Segment::Literal(l) => l.clone(),
+                Segment::Field(Field::Time) => time.to_owned(),
+                Segment::Field(Field::Id) => r.id.to_string(),
+                Segment::Field(Field::Duration) => r.duration.to_string(),
+                Segment::Field(Field::ClientSocket) => r.client_socket.clone(),
+                Segment::Field(Field::ClientName) => r.client_name.clone(),
+                Segment::Field(Field::Command) => r.command.join(" "),
+                Segment::Field(Field::Server) => server.to_owned(),
+                Segment::Field(Field::Count) => count.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let r = SlowlogRecord {
+            id: 1,
+            time: 2,
+            duration: 3,
+            command: vec!["GET".to_owned(), "key".to_owned()],
+            client_socket: "127.0.0.1:10000".to_owned(),
+            client_name: "client".to_owned(),
+        };
+        let t = Template::parse("{{{time}}} {duration}us {command} ({client_name})").unwrap();
+        assert_eq!(t.render(&r, "2", "", 1), "{2} 3us GET key (client)");
+        let t = Template::parse("{command} x{count}").unwrap();
+        assert_eq!(t.render(&r, "2", "", 3), "GET key x3")
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Template::parse("{unknown}").is_err());
+        assert!(Template::parse("{time").is_err());
+        assert!(Template::parse("time}").is_err());
+    }
+}
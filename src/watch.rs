@@ -0,0 +1,45 @@
+// Moves the cursor to the top left corner and clears the terminal
+pub const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// Lays out cells in columns as wide as their widest cell, separated by two spaces
+pub fn render_table(columns: &[&str], rows: &[Vec<String>], header: bool) -> String {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count())
+        }
+    }
+    let mut table = String::new();
+    let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+    for row in std::iter::once(&columns).filter(|_| header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n')
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table() {
+        let rows = vec![
+            vec!["1".to_owned(), "GET key".to_owned()],
+            vec!["10".to_owned(), "ключ".to_owned()],
+        ];
+        assert_eq!(
+            render_table(&["id", "command"], &rows, true),
+            "id  command\n1   GET key\n10  ключ\n"
+        );
+        assert_eq!(
+            render_table(&["id", "command"], &rows, false),
+            "1   GET key\n10  ключ\n"
+        );
+    }
+}